@@ -0,0 +1,64 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Message sends through `objc_msgSend` compared with `ImpCache`.
+
+extern crate objc;
+extern crate test;
+
+use objc::imp::ImpCache;
+use objc::{Id, NSClassObject, Selector, class, msg_send, selector};
+use test::{Bencher, black_box};
+
+#[bench]
+fn bench_msg_send(b: &mut Bencher) {
+    unsafe {
+        let object = NSClassObject::c_new(class("NSObject"));
+        let sel = selector("hash");
+        b.iter(|| {
+            for _ in range(0u, 1000) {
+                black_box(msg_send::<uint>()(object, sel));
+            }
+        });
+    }
+}
+
+#[bench]
+fn bench_imp_cache(b: &mut Bencher) {
+    unsafe {
+        let object = NSClassObject::c_new(class("NSObject"));
+        let sel = selector("hash");
+        let mut cache = ImpCache::new();
+        b.iter(|| {
+            for _ in range(0u, 1000) {
+                black_box::<uint>(cache.send(object, sel, ()));
+            }
+        });
+    }
+}
+
+#[bench]
+fn bench_imp_direct(b: &mut Bencher) {
+    unsafe {
+        let object = NSClassObject::c_new(class("NSObject"));
+        let sel = selector("hash");
+        let mut cache = ImpCache::new();
+        let imp = cache.get(object.get_class(), sel).cast::<extern fn(Id, Selector) -> uint>();
+        b.iter(|| {
+            for _ in range(0u, 1000) {
+                black_box(imp(object, sel));
+            }
+        });
+    }
+}
@@ -0,0 +1,23 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compiles the Objective-C shim used to catch exceptions.
+
+extern crate gcc;
+
+use std::default::Default;
+
+fn main() {
+    gcc::compile_library("libobjc_rs_exception.a", &Default::default(), &["src/exception.m"]);
+}
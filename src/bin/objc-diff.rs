@@ -0,0 +1,105 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Captures and compares runtime metadata snapshots.
+//!
+//! ~~~
+//! objc-diff --capture OUT [--image PATH]...
+//! objc-diff OLD NEW
+//! ~~~
+//!
+//! The first form loads each `--image` and writes a snapshot of the process
+//! to `OUT`. The second prints the changes between two snapshots, and exits
+//! with a status of 1 if any of them are breaking.
+
+extern crate objc;
+
+use std::dynamic_lib::DynamicLibrary;
+use std::os;
+
+use objc::diff::diff;
+use objc::metadata::Snapshot;
+
+#[link(name = "Foundation", kind = "framework")]
+extern {}
+
+fn main() {
+    let args = os::args();
+    let mut capture = None;
+    let mut images = Vec::new();
+    let mut paths = Vec::new();
+    let mut iter = args.iter().skip(1);
+    loop {
+        match iter.next().map(|arg| arg.as_slice()) {
+            Some("--capture") => match iter.next() {
+                Some(path) => capture = Some(path.clone()),
+                None => return usage(args[0].as_slice()),
+            },
+            Some("--image") => match iter.next() {
+                Some(path) => images.push(path.clone()),
+                None => return usage(args[0].as_slice()),
+            },
+            Some("-h") | Some("--help") => return usage(args[0].as_slice()),
+            Some(path) => paths.push(path.to_string()),
+            None => break,
+        }
+    }
+
+    match (capture, paths.as_slice()) {
+        (Some(out), []) => {
+            // Keep the libraries loaded until the snapshot has been taken.
+            let mut libraries = Vec::new();
+            for path in images.iter() {
+                match DynamicLibrary::open(Some(&Path::new(path.as_slice()))) {
+                    Ok(library) => libraries.push(library),
+                    Err(err) => return error(format!("could not load `{}`: {}", path, err)),
+                }
+            }
+            let snapshot = unsafe { Snapshot::capture() };
+            match snapshot.save(&Path::new(out.as_slice())) {
+                Ok(()) => {}
+                Err(err) => error(format!("could not write `{}`: {}", out, err)),
+            }
+        }
+        (None, [ref old, ref new]) if images.is_empty() => {
+            let old = match Snapshot::load(&Path::new(old.as_slice())) {
+                Ok(snapshot) => snapshot,
+                Err(err) => return error(format!("could not read `{}`: {}", old, err)),
+            };
+            let new = match Snapshot::load(&Path::new(new.as_slice())) {
+                Ok(snapshot) => snapshot,
+                Err(err) => return error(format!("could not read `{}`: {}", new, err)),
+            };
+            let changes = diff(&old, &new);
+            for change in changes.iter() {
+                println!("{}", change);
+            }
+            if changes.iter().any(|change| change.is_breaking()) {
+                os::set_exit_status(1);
+            }
+        }
+        _ => usage(args[0].as_slice()),
+    }
+}
+
+fn error(msg: String) {
+    let _ = writeln!(&mut std::io::stderr(), "error: {}", msg);
+    os::set_exit_status(1);
+}
+
+fn usage(program: &str) {
+    let _ = writeln!(&mut std::io::stderr(), "usage: {} --capture OUT [--image PATH]...", program);
+    let _ = writeln!(&mut std::io::stderr(), "       {} OLD NEW", program);
+    os::set_exit_status(2);
+}
@@ -0,0 +1,84 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Prints `class-dump` style headers for loaded Objective-C classes.
+//!
+//! ~~~
+//! objc-dump [--image PATH]... [CLASS]...
+//! ~~~
+//!
+//! Each `--image` is loaded and dumped in full. Class names are looked up
+//! after all of the images have been loaded.
+
+extern crate objc;
+
+use std::dynamic_lib::DynamicLibrary;
+use std::os;
+
+#[link(name = "Foundation", kind = "framework")]
+extern {}
+
+fn main() {
+    let args = os::args();
+    let mut images = Vec::new();
+    let mut classes = Vec::new();
+    let mut iter = args.iter().skip(1);
+    loop {
+        match iter.next().map(|arg| arg.as_slice()) {
+            Some("--image") => match iter.next() {
+                Some(path) => images.push(path.clone()),
+                None => return usage(args[0].as_slice()),
+            },
+            Some("-h") | Some("--help") => return usage(args[0].as_slice()),
+            Some(name) => classes.push(name.to_string()),
+            None => break,
+        }
+    }
+    if images.is_empty() && classes.is_empty() {
+        return usage(args[0].as_slice());
+    }
+
+    // Keep the libraries loaded for the lifetime of the process.
+    let mut libraries = Vec::new();
+    for path in images.iter() {
+        match DynamicLibrary::open(Some(&Path::new(path.as_slice()))) {
+            Ok(library) => libraries.push(library),
+            Err(err) => {
+                let _ = writeln!(&mut std::io::stderr(), "error: could not load `{}`: {}", path, err);
+                os::set_exit_status(1);
+                return;
+            }
+        }
+    }
+
+    unsafe {
+        for path in images.iter() {
+            print!("{}", objc::dump::dump_image(path.as_slice()));
+        }
+        for name in classes.iter() {
+            let class = objc::Class::look_up(name.as_slice());
+            if class == objc::Class::nil() {
+                let _ = writeln!(&mut std::io::stderr(), "error: no class named `{}`", name);
+                os::set_exit_status(1);
+                continue;
+            }
+            println!("{}", objc::dump::dump_class(class));
+        }
+    }
+}
+
+fn usage(program: &str) {
+    let _ = writeln!(&mut std::io::stderr(), "usage: {} [--image PATH]... [CLASS]...", program);
+    os::set_exit_status(2);
+}
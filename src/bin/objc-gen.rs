@@ -0,0 +1,117 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Generates Rust wrappers for Objective-C classes, following the
+//! conventions described in the README.
+//!
+//! ~~~
+//! objc-gen [--namespace PREFIX] [--image PATH]... [--json FILE]... [CLASS]...
+//! ~~~
+//!
+//! Classes are described either by a JSON file, containing a class
+//! description or a list of them, or by name, in which case their metadata is
+//! read from the runtime after loading each `--image`.
+
+extern crate objc;
+extern crate serialize;
+
+use std::dynamic_lib::DynamicLibrary;
+use std::io::File;
+use std::os;
+
+use objc::generate::{Options, generate};
+use objc::metadata::ClassInfo;
+use serialize::json;
+
+#[link(name = "Foundation", kind = "framework")]
+extern {}
+
+fn main() {
+    let args = os::args();
+    let mut options = Options::new();
+    let mut images = Vec::new();
+    let mut files = Vec::new();
+    let mut classes = Vec::new();
+    let mut iter = args.iter().skip(1);
+    loop {
+        match iter.next().map(|arg| arg.as_slice()) {
+            Some("--namespace") => match iter.next() {
+                Some(namespace) => options.namespace = Some(namespace.clone()),
+                None => return usage(args[0].as_slice()),
+            },
+            Some("--image") => match iter.next() {
+                Some(path) => images.push(path.clone()),
+                None => return usage(args[0].as_slice()),
+            },
+            Some("--json") => match iter.next() {
+                Some(path) => files.push(path.clone()),
+                None => return usage(args[0].as_slice()),
+            },
+            Some("-h") | Some("--help") => return usage(args[0].as_slice()),
+            Some(name) => classes.push(name.to_string()),
+            None => break,
+        }
+    }
+    if files.is_empty() && classes.is_empty() {
+        return usage(args[0].as_slice());
+    }
+
+    let mut infos = Vec::new();
+    for path in files.iter() {
+        let contents = match File::open(&Path::new(path.as_slice())).read_to_string() {
+            Ok(contents) => contents,
+            Err(err) => return error(format!("could not read `{}`: {}", path, err)),
+        };
+        // Accept either a single class description or a list of them.
+        match json::decode::<Vec<ClassInfo>>(contents.as_slice()) {
+            Ok(list) => infos.extend(list.into_iter()),
+            Err(_) => match ClassInfo::from_json(contents.as_slice()) {
+                Ok(info) => infos.push(info),
+                Err(err) => return error(format!("could not parse `{}`: {}", path, err)),
+            },
+        }
+    }
+
+    // Keep the libraries loaded for the lifetime of the process.
+    let mut libraries = Vec::new();
+    for path in images.iter() {
+        match DynamicLibrary::open(Some(&Path::new(path.as_slice()))) {
+            Ok(library) => libraries.push(library),
+            Err(err) => return error(format!("could not load `{}`: {}", path, err)),
+        }
+    }
+    for name in classes.iter() {
+        unsafe {
+            let class = objc::Class::look_up(name.as_slice());
+            if class == objc::Class::nil() {
+                return error(format!("no class named `{}`", name));
+            }
+            infos.push(ClassInfo::from_class(class));
+        }
+    }
+
+    let wrappers: Vec<String> = infos.iter().map(|info| generate(info, &options)).collect();
+    print!("{}", wrappers.connect("\n"));
+}
+
+fn error(msg: String) {
+    let _ = writeln!(&mut std::io::stderr(), "error: {}", msg);
+    os::set_exit_status(1);
+}
+
+fn usage(program: &str) {
+    let _ = writeln!(&mut std::io::stderr(),
+                     "usage: {} [--namespace PREFIX] [--image PATH]... [--json FILE]... [CLASS]...", program);
+    os::set_exit_status(2);
+}
@@ -0,0 +1,520 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Objective-C blocks.
+//!
+//! Blocks are laid out according to the
+//! [Block ABI](http://clang.llvm.org/docs/Block-ABI-Apple.html). A block
+//! received from Objective-C can be invoked through a `&Block<A, R>`, where
+//! `A` is a tuple of the argument types and `R` is the return type:
+//!
+//! ~~~rust
+//! unsafe fn run_completion(block: &Block<(Id, uint), ()>, result: Id) {
+//!     block.call((result, 0));
+//! }
+//! ~~~
+//!
+//! Rust closures are built as a `ConcreteBlock`, and copied to the heap as
+//! an `RcBlock` to be passed to Objective-C:
+//!
+//! ~~~rust
+//! let offset = 10;
+//! let block = ConcreteBlock::new(move |&: a: c_int| a + offset);
+//! let block = block.copy();
+//! assert_eq!(block.call((5,)), 15);
+//! ~~~
+//!
+//! Functions that capture nothing can be declared as global blocks with the
+//! `global_block!` macro.
+
+use libc::{c_char, c_int, c_ulong, c_void};
+use std::mem;
+use std::ops::Deref;
+use std::ptr;
+
+use {Id, returns_in_memory};
+
+#[allow(non_upper_case_globals)]
+#[link(name = "System", kind = "dylib")]
+extern {
+    pub static _NSConcreteStackBlock: c_void;
+    pub static _NSConcreteGlobalBlock: c_void;
+    pub static _NSConcreteMallocBlock: c_void;
+
+    pub fn _Block_copy(block: *const c_void) -> *mut c_void;
+    pub fn _Block_release(block: *const c_void);
+    pub fn _Block_object_assign(dst: *mut c_void, src: *const c_void, flags: c_int);
+    pub fn _Block_object_dispose(object: *const c_void, flags: c_int);
+}
+
+/// The descriptor has `copy_helper` and `dispose_helper` fields.
+pub const BLOCK_HAS_COPY_DISPOSE: c_int = 1 << 25;
+/// The helpers run C++ constructors and destructors.
+pub const BLOCK_HAS_CTOR: c_int = 1 << 26;
+/// The block is statically allocated, and copying it is a no-op.
+pub const BLOCK_IS_GLOBAL: c_int = 1 << 28;
+/// The block returns a struct in memory.
+pub const BLOCK_HAS_STRET: c_int = 1 << 29;
+/// The descriptor has a `signature` field.
+pub const BLOCK_HAS_SIGNATURE: c_int = 1 << 30;
+
+/// `_Block_object_assign` flag for a captured object.
+pub const BLOCK_FIELD_IS_OBJECT: c_int = 3;
+/// `_Block_object_assign` flag for a captured block.
+pub const BLOCK_FIELD_IS_BLOCK: c_int = 7;
+
+/// The header shared by every block literal.
+#[repr(C)]
+pub struct BlockLiteral {
+    pub isa: *const c_void,
+    pub flags: c_int,
+    pub reserved: c_int,
+    /// The function called to invoke the block, taking the block itself as
+    /// its first argument.
+    pub invoke: *mut c_void,
+    pub descriptor: *const BlockDescriptor,
+}
+
+/// A block descriptor. The helper fields are only present if the block's
+/// flags include `BLOCK_HAS_COPY_DISPOSE`.
+#[repr(C)]
+pub struct BlockDescriptor {
+    pub reserved: c_ulong,
+    /// The size of the block literal, including its captured variables.
+    pub size: c_ulong,
+    /// Copies the captured variables of a block moving from the stack to
+    /// the heap, as `copy_helper(dst, src)`.
+    pub copy_helper: Option<unsafe extern fn(*mut c_void, *const c_void)>,
+    /// Releases the captured variables of a heap block.
+    pub dispose_helper: Option<unsafe extern fn(*mut c_void)>,
+}
+
+/// A block taking the arguments `A` and returning `R`.
+///
+/// This type is only used behind a reference or a pointer; its layout is
+/// that of a `BlockLiteral`, followed by captured variables.
+#[repr(C)]
+pub struct Block<A, R> {
+    _base: BlockLiteral,
+}
+
+impl<A: BlockArguments, R> Block<A, R> {
+    /// Invokes the block with the given arguments.
+    pub unsafe fn call(&self, args: A) -> R {
+        args.call_block(self)
+    }
+}
+
+impl<A, R> Block<A, R> {
+    /// Returns the block as an object pointer, to pass to Objective-C.
+    #[inline]
+    pub fn as_id(&self) -> Id {
+        Id { raw: self as *const Block<A, R> as *mut _ }
+    }
+
+    /// Returns the header of the block.
+    #[inline]
+    pub fn literal(&self) -> &BlockLiteral {
+        &self._base
+    }
+}
+
+/// Types that can be passed as the arguments of a block: tuples of up to
+/// twelve elements.
+pub trait BlockArguments {
+    /// Invokes `block` with these arguments.
+    unsafe fn call_block<R>(self, block: &Block<Self, R>) -> R;
+}
+
+macro_rules! block_args_impl(
+    ($($a:ident : $t:ident),*) => (
+        impl<$($t),*> BlockArguments for ($($t,)*) {
+            #[allow(non_snake_case)]
+            unsafe fn call_block<R>(self, block: &Block<($($t,)*), R>) -> R {
+                let invoke: unsafe extern fn(*const Block<($($t,)*), R> $(, $t)*) -> R = {
+                    mem::transmute(block.literal().invoke)
+                };
+                let ($($a,)*) = self;
+                invoke(block $(, $a)*)
+            }
+        }
+    );
+)
+
+block_args_impl!()
+block_args_impl!(a: A)
+block_args_impl!(a: A, b: B)
+block_args_impl!(a: A, b: B, c: C)
+block_args_impl!(a: A, b: B, c: C, d: D)
+block_args_impl!(a: A, b: B, c: C, d: D, e: E)
+block_args_impl!(a: A, b: B, c: C, d: D, e: E, f: F)
+block_args_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G)
+block_args_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H)
+block_args_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I)
+block_args_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J)
+block_args_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K)
+block_args_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L)
+
+/// An owned, reference counted block on the heap.
+///
+/// Cloning the `RcBlock` retains the block, and dropping it releases it,
+/// running the block's dispose helper once the last reference is gone.
+pub struct RcBlock<A, R> {
+    ptr: *mut Block<A, R>,
+}
+
+impl<A, R> RcBlock<A, R> {
+    /// Copies a block to the heap with `_Block_copy`, or retains it if it is
+    /// already there.
+    pub unsafe fn copy(ptr: *const Block<A, R>) -> RcBlock<A, R> {
+        RcBlock { ptr: _Block_copy(ptr as *const c_void) as *mut Block<A, R> }
+    }
+
+    /// Takes ownership of a heap block that has already been retained, such
+    /// as one returned from a `copy` method.
+    pub unsafe fn from_raw(ptr: *mut Block<A, R>) -> RcBlock<A, R> {
+        RcBlock { ptr: ptr }
+    }
+
+    #[inline]
+    pub fn as_ptr(&self) -> *mut Block<A, R> {
+        self.ptr
+    }
+}
+
+impl<A, R> Clone for RcBlock<A, R> {
+    fn clone(&self) -> RcBlock<A, R> {
+        unsafe { RcBlock::copy(self.ptr as *const Block<A, R>) }
+    }
+}
+
+impl<A, R> Deref<Block<A, R>> for RcBlock<A, R> {
+    fn deref<'a>(&'a self) -> &'a Block<A, R> {
+        unsafe { &*self.ptr }
+    }
+}
+
+#[unsafe_destructor]
+impl<A, R> Drop for RcBlock<A, R> {
+    fn drop(&mut self) {
+        unsafe { _Block_release(self.ptr as *const c_void); }
+    }
+}
+
+/// Closures that can be converted into a `ConcreteBlock` taking the
+/// arguments `A` and returning `R`.
+pub trait IntoConcreteBlock<A: BlockArguments, R> {
+    fn into_concrete_block(self) -> ConcreteBlock<A, R, Self>;
+}
+
+macro_rules! concrete_block_impl(
+    ($f:ident $(, $a:ident : $t:ident)*) => (
+        impl<$($t,)* R, X> IntoConcreteBlock<($($t,)*), R> for X where X: Fn($($t),*) -> R {
+            fn into_concrete_block(self) -> ConcreteBlock<($($t,)*), R, X> {
+                #[allow(non_snake_case)]
+                unsafe extern fn $f<$($t,)* R, X>(block_ptr: *mut ConcreteBlock<($($t,)*), R, X>
+                                                  $(, $a: $t)*) -> R
+                                                  where X: Fn($($t),*) -> R {
+                    let block = &*block_ptr;
+                    (block.closure)($($a),*)
+                }
+
+                unsafe { ConcreteBlock::with_invoke($f::<$($t,)* R, X> as *mut c_void, self) }
+            }
+        }
+    );
+)
+
+concrete_block_impl!(concrete_block_invoke_args0)
+concrete_block_impl!(concrete_block_invoke_args1, a: A)
+concrete_block_impl!(concrete_block_invoke_args2, a: A, b: B)
+concrete_block_impl!(concrete_block_invoke_args3, a: A, b: B, c: C)
+concrete_block_impl!(concrete_block_invoke_args4, a: A, b: B, c: C, d: D)
+concrete_block_impl!(concrete_block_invoke_args5, a: A, b: B, c: C, d: D, e: E)
+concrete_block_impl!(concrete_block_invoke_args6, a: A, b: B, c: C, d: D, e: E, f: F)
+concrete_block_impl!(concrete_block_invoke_args7, a: A, b: B, c: C, d: D, e: E, f: F, g: G)
+concrete_block_impl!(concrete_block_invoke_args8, a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H)
+concrete_block_impl!(concrete_block_invoke_args9, a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I)
+concrete_block_impl!(concrete_block_invoke_args10, a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J)
+concrete_block_impl!(concrete_block_invoke_args11, a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K)
+concrete_block_impl!(concrete_block_invoke_args12, a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L)
+
+/// A block created from a Rust closure, laid out like a block literal on the
+/// stack.
+///
+/// The captured state is owned by the block: it is moved to the heap by
+/// `copy`, and dropped by the block's dispose helper once Objective-C
+/// releases the last reference to the copy.
+#[repr(C)]
+pub struct ConcreteBlock<A, R, F> {
+    base: BlockLiteral,
+    descriptor: Box<ConcreteBlockDescriptor>,
+    closure: F,
+}
+
+/// The descriptor of a `ConcreteBlock`, which has a signature so that the
+/// block can be flagged `BLOCK_HAS_STRET`.
+#[repr(C)]
+struct ConcreteBlockDescriptor {
+    base: BlockDescriptor,
+    signature: *const c_char,
+}
+
+/// The signature given to blocks returning a struct in memory. The argument
+/// types are not known, but `imp_implementationWithBlock` only needs the
+/// signature to be present to honour `BLOCK_HAS_STRET`.
+static STRET_SIGNATURE: &'static [u8] = b"{?}@?\0";
+
+impl<A: BlockArguments, R, F: IntoConcreteBlock<A, R>> ConcreteBlock<A, R, F> {
+    /// Creates a block that calls `closure`.
+    pub fn new(closure: F) -> ConcreteBlock<A, R, F> {
+        closure.into_concrete_block()
+    }
+}
+
+impl<A, R, F> ConcreteBlock<A, R, F> {
+    /// Creates a block that calls `invoke`, which must have the signature
+    /// `unsafe extern fn(*mut ConcreteBlock<A, R, F>, ...) -> R` for the
+    /// arguments `A`.
+    ///
+    /// If `R` is returned in memory, the block is flagged `BLOCK_HAS_STRET`,
+    /// so that method implementations created from it pass the return
+    /// pointer through.
+    pub unsafe fn with_invoke(invoke: *mut c_void, closure: F) -> ConcreteBlock<A, R, F> {
+        let descriptor = box ConcreteBlockDescriptor {
+            base: BlockDescriptor {
+                reserved: 0,
+                size: mem::size_of::<ConcreteBlock<A, R, F>>() as c_ulong,
+                copy_helper: Some(mem::transmute(block_copy_helper::<ConcreteBlock<A, R, F>>)),
+                dispose_helper: Some(mem::transmute(block_dispose_helper::<ConcreteBlock<A, R, F>>)),
+            },
+            signature: STRET_SIGNATURE.as_ptr() as *const c_char,
+        };
+        let flags = if returns_in_memory::<R>() {
+            BLOCK_HAS_COPY_DISPOSE | BLOCK_HAS_STRET | BLOCK_HAS_SIGNATURE
+        } else {
+            BLOCK_HAS_COPY_DISPOSE
+        };
+        ConcreteBlock {
+            base: BlockLiteral {
+                isa: &_NSConcreteStackBlock,
+                flags: flags,
+                reserved: 0,
+                invoke: invoke,
+                descriptor: &descriptor.base,
+            },
+            descriptor: descriptor,
+            closure: closure,
+        }
+    }
+}
+
+impl<A, R, F: 'static> ConcreteBlock<A, R, F> {
+    /// Moves the block to the heap, returning a reference counted pointer to
+    /// the copy.
+    pub fn copy(self) -> RcBlock<A, R> {
+        unsafe {
+            let copied = RcBlock::from_raw(_Block_copy(&self as *const _ as *const c_void) as *mut Block<A, R>);
+            // The copy now owns the descriptor and the closure.
+            mem::forget(self);
+            copied
+        }
+    }
+}
+
+impl<A: BlockArguments, R, F> ConcreteBlock<A, R, F> {
+    /// Invokes the block with the given arguments.
+    ///
+    /// A `ConcreteBlock` cannot be borrowed as a `&Block`, since Objective-C
+    /// would copy it without taking ownership of the closure; use `copy`.
+    pub unsafe fn call(&self, args: A) -> R {
+        args.call_block(mem::transmute(&self.base))
+    }
+}
+
+/// The copy helper of a `ConcreteBlock`. The only stack block the runtime
+/// copies is the one passed by `ConcreteBlock::copy`, which forgets the
+/// original, so the bytes copied by `_Block_copy` already own the closure
+/// and descriptor.
+unsafe extern fn block_copy_helper<B>(_dst: *mut B, _src: *const B) {}
+
+/// The dispose helper of a `ConcreteBlock`, which drops the captured state of
+/// a heap block.
+unsafe extern fn block_dispose_helper<B>(block: *mut B) {
+    drop(ptr::read(block));
+}
+
+/// A block with no captured state, created with `global_block!`.
+///
+/// `I` is the type of the block's invoke function. Global blocks are never
+/// copied or freed by the runtime.
+#[repr(C)]
+pub struct GlobalBlock<A, R, I> {
+    pub isa: &'static c_void,
+    pub flags: c_int,
+    pub reserved: c_int,
+    pub invoke: I,
+    pub descriptor: &'static BlockDescriptor,
+}
+
+unsafe impl<A, R, I> Sync for GlobalBlock<A, R, I> {}
+
+impl<A, R, I> Deref<Block<A, R>> for GlobalBlock<A, R, I> {
+    fn deref<'a>(&'a self) -> &'a Block<A, R> {
+        unsafe { mem::transmute(self) }
+    }
+}
+
+/// The descriptor shared by all global blocks.
+pub static GLOBAL_BLOCK_DESCRIPTOR: BlockDescriptor = BlockDescriptor {
+    reserved: 0,
+    size: GLOBAL_BLOCK_SIZE,
+    copy_helper: None,
+    dispose_helper: None,
+};
+
+#[cfg(target_word_size = "32")]
+const GLOBAL_BLOCK_SIZE: c_ulong = 20;
+#[cfg(target_word_size = "64")]
+const GLOBAL_BLOCK_SIZE: c_ulong = 32;
+
+/// Declares a static global block, along with its invoke function.
+///
+/// ~~~rust
+/// global_block! {
+///     static ADD_BLOCK = fn add(a: c_int, b: c_int) -> c_int {
+///         a + b
+///     }
+/// }
+///
+/// assert_eq!(ADD_BLOCK.call((2, 3)), 5);
+/// ~~~
+#[macro_export]
+macro_rules! global_block(
+    (static $name:ident = fn $invoke:ident($($a:ident : $t:ty),*) -> $r:ty $body:block) => (
+        #[allow(non_snake_case)]
+        unsafe extern fn $invoke(_block: *const $crate::block::Block<($($t,)*), $r> $(, $a: $t)*) -> $r $body
+
+        #[allow(non_upper_case_globals)]
+        static $name: $crate::block::GlobalBlock<
+            ($($t,)*), $r,
+            unsafe extern fn(*const $crate::block::Block<($($t,)*), $r> $(, $t)*) -> $r
+        > = $crate::block::GlobalBlock {
+            isa: &$crate::block::_NSConcreteGlobalBlock,
+            flags: $crate::block::BLOCK_IS_GLOBAL,
+            reserved: 0,
+            invoke: $invoke,
+            descriptor: &$crate::block::GLOBAL_BLOCK_DESCRIPTOR,
+        };
+    );
+)
+
+#[cfg(test)]
+mod tests {
+    use libc::{c_int, c_ulong, c_void};
+    use std::cell::Cell;
+    use std::mem;
+    use std::rc::Rc;
+    use super::{Block, BlockDescriptor, BlockLiteral, ConcreteBlock, RcBlock};
+    use super::{BLOCK_IS_GLOBAL, _NSConcreteGlobalBlock};
+
+    static DESCRIPTOR: BlockDescriptor = BlockDescriptor {
+        reserved: 0,
+        size: 32 as c_ulong,
+        copy_helper: None,
+        dispose_helper: None,
+    };
+
+    unsafe extern fn add(_block: *const Block<(c_int, c_int), c_int>, a: c_int, b: c_int) -> c_int {
+        a + b
+    }
+
+    unsafe fn add_block() -> BlockLiteral {
+        BlockLiteral {
+            isa: &_NSConcreteGlobalBlock,
+            flags: BLOCK_IS_GLOBAL,
+            reserved: 0,
+            invoke: add as *mut c_void,
+            descriptor: &DESCRIPTOR,
+        }
+    }
+
+    #[test]
+    pub fn test_call_block() {
+        unsafe {
+            let literal = add_block();
+            let block: &Block<(c_int, c_int), c_int> = mem::transmute(&literal);
+            assert_eq!(block.call((2, 3)), 5);
+        }
+    }
+
+    #[test]
+    pub fn test_concrete_block() {
+        unsafe {
+            let offset = 10;
+            let block = ConcreteBlock::new(move |&: a: c_int| a + offset);
+            assert_eq!(block.call((5,)), 15);
+
+            let copied = block.copy();
+            assert_eq!(copied.call((6,)), 16);
+            assert_eq!(copied.clone().call((7,)), 17);
+        }
+    }
+
+    #[test]
+    pub fn test_concrete_block_drops_state() {
+        struct Flag(Rc<Cell<bool>>);
+        impl Drop for Flag {
+            fn drop(&mut self) {
+                let Flag(ref dropped) = *self;
+                dropped.set(true);
+            }
+        }
+
+        let dropped = Rc::new(Cell::new(false));
+        let flag = Flag(dropped.clone());
+        let block = ConcreteBlock::new(move |&:| { let _ = &flag; });
+        let copied = block.copy();
+        assert!(!dropped.get());
+        drop(copied);
+        assert!(dropped.get());
+    }
+
+    global_block! {
+        static MUL_BLOCK = fn mul(a: c_int, b: c_int) -> c_int {
+            a * b
+        }
+    }
+
+    #[test]
+    pub fn test_global_block() {
+        unsafe {
+            assert_eq!(MUL_BLOCK.call((3, 4)), 12);
+            let copied = RcBlock::copy(&*MUL_BLOCK);
+            assert_eq!(copied.as_ptr() as *const _, &*MUL_BLOCK as *const _);
+        }
+    }
+
+    #[test]
+    pub fn test_copy_global_block() {
+        unsafe {
+            let literal = add_block();
+            let block: *const Block<(c_int, c_int), c_int> = mem::transmute(&literal);
+            let copied = RcBlock::copy(block);
+            // Copying a global block returns the block itself.
+            assert_eq!(copied.as_ptr() as *const _, block);
+            assert_eq!(copied.clone().call((4, 5)), 9);
+        }
+    }
+}
@@ -0,0 +1,246 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Uniquely named classes that are disposed of when no longer used.
+//!
+//! A `ClassPair` is useful in tests, which may create classes freely
+//! without their names clashing with those of earlier runs in the same
+//! process:
+//!
+//! ~~~rust
+//! let pair = ClassPair::new(class("NSObject"), "RSTestView").unwrap();
+//! pair.class().add_method_fn(selector("draw"), "v@:", |&: this: Id| { ... });
+//! pair.register();
+//! ...
+//! // The class is disposed of here, or reported as leaked if it is still in
+//! // use.
+//! ~~~
+
+use std::io::stderr;
+use std::mem;
+use std::sync::{Arc, MUTEX_INIT, StaticMutex};
+use std::sync::atomic::{AtomicUint, SeqCst};
+
+use {Class, Id, remove_block, selector, take_block_methods};
+
+static LEAKS_LOCK: StaticMutex = MUTEX_INIT;
+static mut LEAKS: *mut Vec<String> = 0 as *mut Vec<String>;
+
+/// Returns the names of the classes that were not disposed of because they
+/// were still in use.
+pub fn leaked_classes() -> Vec<String> {
+    unsafe {
+        let _guard = LEAKS_LOCK.lock();
+        if LEAKS.is_null() { Vec::new() } else { (*LEAKS).clone() }
+    }
+}
+
+fn report_leak(name: &str, reason: &str) {
+    let _ = writeln!(&mut stderr(), "objc: leaking class {}: {}", name, reason);
+    unsafe {
+        let _guard = LEAKS_LOCK.lock();
+        if LEAKS.is_null() {
+            LEAKS = mem::transmute(box Vec::<String>::new());
+        }
+        (*LEAKS).push(name.to_string());
+    }
+}
+
+/// A uniquely named class, which is disposed of when the `ClassPair` is
+/// dropped if it is safe to do so: that is, if there are no live instances
+/// of the class or of any subclass, and no subclasses. Otherwise the class
+/// is leaked, and reported by `leaked_classes`.
+///
+/// The live instances are counted by overriding `+allocWithZone:` and
+/// `-dealloc`, so the class cannot define these itself. Only registered
+/// subclasses are seen; an unregistered subclass of a disposed class must
+/// never be registered.
+///
+/// Disposing of the class releases the blocks of methods added to it, or
+/// its metaclass, with `Class::add_method_fn` and the like. Block
+/// implementations added by other means, such as a `Swizzle`, are left to
+/// their owners.
+pub struct ClassPair {
+    class: Class,
+    live_instances: Arc<AtomicUint>,
+    is_disposed: bool,
+}
+
+impl ClassPair {
+    /// Allocates a subclass of `superclass`, named `prefix` followed by a
+    /// number. The class must be registered with `register` before it is
+    /// instantiated.
+    ///
+    /// Fails if the runtime cannot subclass `superclass`, such as when it is
+    /// a metaclass.
+    pub unsafe fn new(superclass: Class, prefix: &str) -> Result<ClassPair, String> {
+        let class = superclass.allocate_unique_class_pair(prefix, 0);
+        if class == Class::nil() {
+            return Err(format!("cannot allocate a subclass of {}", superclass));
+        }
+        let live_instances = Arc::new(AtomicUint::new(0));
+
+        let meta = class.get_meta_class();
+        let allocated = live_instances.clone();
+        meta.add_method_fn(selector("allocWithZone:"), "@@:^v", move |&: this: Id, zone: *mut u8| -> Id {
+            let object: Id = msg_send_super!(meta, this, "allocWithZone:", zone);
+            if object != Id::nil() {
+                allocated.fetch_add(1, SeqCst);
+            }
+            object
+        });
+        let deallocated = live_instances.clone();
+        class.add_method_fn(selector("dealloc"), "v@:", move |&: this: Id| {
+            deallocated.fetch_sub(1, SeqCst);
+            let _: () = msg_send_super!(class, this, "dealloc");
+        });
+
+        Ok(ClassPair {
+            class: class,
+            live_instances: live_instances,
+            is_disposed: false,
+        })
+    }
+
+    #[inline]
+    pub fn class(&self) -> Class {
+        self.class
+    }
+
+    pub unsafe fn get_name(&self) -> String {
+        self.class.get_name()
+    }
+
+    /// Registers the class, so that it can be instantiated.
+    pub unsafe fn register(&self) {
+        self.class.register_class_pair();
+    }
+
+    /// The number of instances of the class and its subclasses that have
+    /// been allocated but not deallocated.
+    pub fn live_instances(&self) -> uint {
+        self.live_instances.load(SeqCst)
+    }
+
+    /// Returns the registered classes whose superclass is this class.
+    /// Subclasses still under construction are not found by the runtime.
+    pub unsafe fn subclasses(&self) -> Vec<Class> {
+        let class = self.class;
+        Class::all().filter(|c| c.get_super_class() == class).collect()
+    }
+
+    /// Disposes of the class now, or returns why it cannot be, in which case
+    /// the class is leaked.
+    pub unsafe fn dispose(mut self) -> Result<(), String> {
+        self.is_disposed = true;
+        self.try_dispose()
+    }
+
+    unsafe fn try_dispose(&self) -> Result<(), String> {
+        let name = self.get_name();
+        let reason = match (self.live_instances(), self.subclasses().len()) {
+            (0, 0) => None,
+            (n, 0) => Some(format!("{} live instances", n)),
+            (_, n) => Some(format!("{} subclasses", n)),
+        };
+        match reason {
+            Some(reason) => {
+                report_leak(name.as_slice(), reason.as_slice());
+                return Err(reason);
+            }
+            None => {}
+        }
+
+        // Release the blocks of methods added with `add_method_fn` and the
+        // like, which the runtime does not own.
+        for &class in [self.class, self.class.get_meta_class()].iter() {
+            for &imp in take_block_methods(class).iter() {
+                remove_block(imp);
+            }
+        }
+        self.class.dispose_class_pair();
+        Ok(())
+    }
+}
+
+impl Drop for ClassPair {
+    fn drop(&mut self) {
+        if !self.is_disposed {
+            self.is_disposed = true;
+            unsafe {
+                let _ = self.try_dispose();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClassPair, leaked_classes};
+    use {Class, Id, class, msg_send, selector};
+    use NSClassObject;
+
+    unsafe fn release(object: Id) {
+        msg_send::<()>()(object, selector("release"))
+    }
+
+    #[test]
+    pub fn test_dispose() {
+        unsafe {
+            let pair = ClassPair::new(class("NSObject"), "RSClassPairDisposeTest").unwrap();
+            assert!(pair.class().add_method_fn(selector("value"), "i@:", |&: _this: Id| 1i32));
+            pair.register();
+            let name = pair.get_name();
+            assert_eq!(Class::get(name.as_slice()), pair.class());
+
+            let object = NSClassObject::c_new(pair.class());
+            assert_eq!(pair.live_instances(), 1);
+            release(object);
+            assert_eq!(pair.live_instances(), 0);
+
+            assert_eq!(pair.dispose(), Ok(()));
+            assert_eq!(Class::get(name.as_slice()), Class::nil());
+        }
+    }
+
+    #[test]
+    pub fn test_leaks() {
+        unsafe {
+            let pair = ClassPair::new(class("NSObject"), "RSClassPairLeakTest").unwrap();
+            pair.register();
+            let name = pair.get_name();
+            let object = NSClassObject::c_new(pair.class());
+            assert!(pair.dispose().is_err());
+            assert!(leaked_classes().contains(&name));
+            assert!(Class::get(name.as_slice()) != Class::nil());
+            release(object);
+
+            let pair = ClassPair::new(class("NSObject"), "RSClassPairLeakTest").unwrap();
+            pair.register();
+            let subclass = pair.class().allocate_unique_class_pair("RSClassPairLeakTestSubclass", 0);
+            subclass.register_class_pair();
+            assert_eq!(pair.subclasses(), vec![subclass]);
+            let name = pair.get_name();
+            drop(pair);
+            assert!(leaked_classes().contains(&name));
+        }
+    }
+
+    #[test]
+    pub fn test_new_fails() {
+        unsafe {
+            assert!(ClassPair::new(Class::get_meta("NSObject"), "RSClassPairMetaTest").is_err());
+        }
+    }
+}
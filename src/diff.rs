@@ -0,0 +1,253 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Comparison of the API surfaces recorded by two metadata snapshots.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use metadata::{ClassInfo, MethodInfo, Snapshot};
+
+/// A reference to a method of a class.
+#[deriving(Clone, PartialEq, Eq)]
+pub struct MethodRef {
+    pub class: String,
+    pub selector: String,
+    pub is_class: bool,
+}
+
+impl fmt::Show for MethodRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}[{} {}]", if self.is_class { "+" } else { "-" }, self.class, self.selector)
+    }
+}
+
+/// A difference between two snapshots.
+#[deriving(Clone, PartialEq, Eq)]
+pub enum Change {
+    ClassAdded(String),
+    ClassRemoved(String),
+    /// The class, and its old and new superclasses.
+    SuperclassChanged(String, Option<String>, Option<String>),
+    MethodAdded(MethodRef),
+    MethodRemoved(MethodRef),
+    /// The method, and its old and new type encodings.
+    MethodEncodingChanged(MethodRef, String, String),
+    /// The class and instance variable names.
+    IvarAdded(String, String),
+    /// The class and instance variable names.
+    IvarRemoved(String, String),
+    /// The class and instance variable names, and the old and new offsets.
+    IvarOffsetChanged(String, String, Option<int>, Option<int>),
+    /// The class and protocol names.
+    ConformanceAdded(String, String),
+    /// The class and protocol names.
+    ConformanceRemoved(String, String),
+}
+
+impl Change {
+    /// Returns `true` if code compiled against the old API could break
+    /// against the new one.
+    pub fn is_breaking(&self) -> bool {
+        match *self {
+            Change::ClassAdded(..) | Change::MethodAdded(..) |
+            Change::IvarAdded(..) | Change::ConformanceAdded(..) => false,
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Show for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn or_none(name: &Option<String>) -> &str {
+            name.as_ref().map_or("(none)", |name| name.as_slice())
+        }
+        fn or_unknown(offset: &Option<int>) -> String {
+            offset.map_or("(unknown)".to_string(), |offset| offset.to_string())
+        }
+        match *self {
+            Change::ClassAdded(ref class) => write!(f, "+ {}", class),
+            Change::ClassRemoved(ref class) => write!(f, "- {}", class),
+            Change::SuperclassChanged(ref class, ref old, ref new) => {
+                write!(f, "~ {} superclass: {} -> {}", class, or_none(old), or_none(new))
+            }
+            Change::MethodAdded(ref method) => write!(f, "+ {}", method),
+            Change::MethodRemoved(ref method) => write!(f, "- {}", method),
+            Change::MethodEncodingChanged(ref method, ref old, ref new) => {
+                write!(f, "~ {}: {} -> {}", method, old, new)
+            }
+            Change::IvarAdded(ref class, ref ivar) => write!(f, "+ {}.{}", class, ivar),
+            Change::IvarRemoved(ref class, ref ivar) => write!(f, "- {}.{}", class, ivar),
+            Change::IvarOffsetChanged(ref class, ref ivar, ref old, ref new) => {
+                write!(f, "~ {}.{} offset: {} -> {}", class, ivar, or_unknown(old), or_unknown(new))
+            }
+            Change::ConformanceAdded(ref class, ref protocol) => write!(f, "+ {} <{}>", class, protocol),
+            Change::ConformanceRemoved(ref class, ref protocol) => write!(f, "- {} <{}>", class, protocol),
+        }
+    }
+}
+
+/// Returns the changes from `old` to `new`, grouped by class in name order.
+///
+/// Classes are matched by name regardless of the image defining them.
+pub fn diff(old: &Snapshot, new: &Snapshot) -> Vec<Change> {
+    let old_classes = classes_by_name(old);
+    let new_classes = classes_by_name(new);
+
+    let mut names: Vec<&String> = old_classes.keys().chain(new_classes.keys()).map(|&name| name).collect();
+    names.sort();
+    names.dedup();
+
+    let mut changes = Vec::new();
+    for name in names.into_iter() {
+        match (old_classes.get(&name), new_classes.get(&name)) {
+            (Some(old), Some(new)) => diff_class(*old, *new, &mut changes),
+            (Some(_), None) => changes.push(Change::ClassRemoved(name.clone())),
+            (None, Some(_)) => changes.push(Change::ClassAdded(name.clone())),
+            (None, None) => unreachable!(),
+        }
+    }
+    changes
+}
+
+fn classes_by_name<'a>(snapshot: &'a Snapshot) -> HashMap<&'a String, &'a ClassInfo> {
+    snapshot.images.iter()
+                   .flat_map(|image| image.classes.iter())
+                   .map(|class| (&class.name, class))
+                   .collect()
+}
+
+/// Appends the changes between two versions of the same class.
+pub fn diff_class(old: &ClassInfo, new: &ClassInfo, changes: &mut Vec<Change>) {
+    let class = &new.name;
+
+    if old.superclass != new.superclass {
+        changes.push(Change::SuperclassChanged(class.clone(), old.superclass.clone(), new.superclass.clone()));
+    }
+
+    diff_methods(class, true, old.class_methods.as_slice(), new.class_methods.as_slice(), changes);
+    diff_methods(class, false, old.instance_methods.as_slice(), new.instance_methods.as_slice(), changes);
+
+    for ivar in old.ivars.iter() {
+        match new.ivars.iter().find(|i| i.name == ivar.name) {
+            Some(new_ivar) if new_ivar.offset != ivar.offset => {
+                changes.push(Change::IvarOffsetChanged(class.clone(), ivar.name.clone(),
+                                                       ivar.offset, new_ivar.offset));
+            }
+            Some(_) => {}
+            None => changes.push(Change::IvarRemoved(class.clone(), ivar.name.clone())),
+        }
+    }
+    for ivar in new.ivars.iter() {
+        if !old.ivars.iter().any(|i| i.name == ivar.name) {
+            changes.push(Change::IvarAdded(class.clone(), ivar.name.clone()));
+        }
+    }
+
+    for protocol in old.protocols.iter() {
+        if !new.protocols.contains(protocol) {
+            changes.push(Change::ConformanceRemoved(class.clone(), protocol.clone()));
+        }
+    }
+    for protocol in new.protocols.iter() {
+        if !old.protocols.contains(protocol) {
+            changes.push(Change::ConformanceAdded(class.clone(), protocol.clone()));
+        }
+    }
+}
+
+fn diff_methods(class: &String, is_class: bool, old: &[MethodInfo], new: &[MethodInfo],
+                changes: &mut Vec<Change>) {
+    let method_ref = |selector: &String| MethodRef {
+        class: class.clone(),
+        selector: selector.clone(),
+        is_class: is_class,
+    };
+    for method in old.iter() {
+        match new.iter().find(|m| m.name == method.name) {
+            Some(new_method) if new_method.types != method.types => {
+                changes.push(Change::MethodEncodingChanged(method_ref(&method.name),
+                                                           method.types.clone(), new_method.types.clone()));
+            }
+            Some(_) => {}
+            None => changes.push(Change::MethodRemoved(method_ref(&method.name))),
+        }
+    }
+    for method in new.iter() {
+        if !old.iter().any(|m| m.name == method.name) {
+            changes.push(Change::MethodAdded(method_ref(&method.name)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Change, MethodRef, diff};
+    use metadata::{ClassInfo, ImageInfo, IvarInfo, MethodInfo, Snapshot};
+
+    fn class(name: &str, protocols: &[&str], ivars: &[(&str, int)], methods: &[(&str, &str)]) -> ClassInfo {
+        ClassInfo {
+            name: name.to_string(),
+            superclass: Some("NSObject".to_string()),
+            protocols: protocols.iter().map(|p| p.to_string()).collect(),
+            ivars: ivars.iter().map(|&(name, offset)| IvarInfo {
+                name: name.to_string(),
+                types: "i".to_string(),
+                offset: Some(offset),
+            }).collect(),
+            properties: Vec::new(),
+            class_methods: Vec::new(),
+            instance_methods: methods.iter().map(|&(name, types)| MethodInfo {
+                name: name.to_string(),
+                types: types.to_string(),
+            }).collect(),
+        }
+    }
+
+    fn snapshot(classes: Vec<ClassInfo>) -> Snapshot {
+        Snapshot {
+            images: vec![ImageInfo { path: "/test".to_string(), classes: classes }],
+            protocols: Vec::new(),
+        }
+    }
+
+    #[test]
+    pub fn test_diff() {
+        let old = snapshot(vec![
+            class("RSFoo", &["NSCopying"], &[("_a", 8), ("_b", 12)], &[("foo", "v16@0:8"), ("bar", "v16@0:8")]),
+            class("RSOld", &[], &[], &[]),
+        ]);
+        let new = snapshot(vec![
+            class("RSFoo", &["NSCoding"], &[("_a", 8), ("_b", 16)], &[("foo", "@16@0:8"), ("baz", "v16@0:8")]),
+            class("RSNew", &[], &[], &[]),
+        ]);
+        let method = |name: &str| MethodRef { class: "RSFoo".to_string(), selector: name.to_string(), is_class: false };
+
+        let changes = diff(&old, &new);
+        assert_eq!(changes, vec![
+            Change::MethodEncodingChanged(method("foo"), "v16@0:8".to_string(), "@16@0:8".to_string()),
+            Change::MethodRemoved(method("bar")),
+            Change::MethodAdded(method("baz")),
+            Change::IvarOffsetChanged("RSFoo".to_string(), "_b".to_string(), Some(12), Some(16)),
+            Change::ConformanceRemoved("RSFoo".to_string(), "NSCopying".to_string()),
+            Change::ConformanceAdded("RSFoo".to_string(), "NSCoding".to_string()),
+            Change::ClassAdded("RSNew".to_string()),
+            Change::ClassRemoved("RSOld".to_string()),
+        ]);
+        assert_eq!(changes[0].to_string().as_slice(), "~ -[RSFoo foo]: v16@0:8 -> @16@0:8");
+        assert!(!changes[2].is_breaking());
+        assert!(changes[3].is_breaking());
+        assert!(diff(&new, &new).is_empty());
+    }
+}
@@ -0,0 +1,190 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Generation of `class-dump` style Objective-C headers from the runtime
+//! metadata of loaded classes.
+
+use {Class, InstanceVariable, Method, Property, class, copy_class_names_for_image};
+use encoding;
+use encoding::Encoding;
+
+/// Returns an `@interface` declaration for every class defined in the image
+/// at the path `image`, sorted by class name.
+pub unsafe fn dump_image(image: &str) -> String {
+    let mut names = copy_class_names_for_image(image);
+    names.sort();
+    let mut header = format!("//\n// Generated from {}\n//\n", image);
+    for name in names.iter() {
+        header.push_str("\n");
+        header.push_str(dump_class(class(name.as_slice())).as_slice());
+    }
+    header
+}
+
+/// Returns an `@interface` declaration for `class`, reconstructed from its
+/// superclass, protocols, instance variables, properties and methods.
+pub unsafe fn dump_class(class: Class) -> String {
+    let mut header = format!("@interface {}", class.get_name());
+
+    let sup = class.get_super_class();
+    if sup != Class::nil() {
+        header.push_str(format!(" : {}", sup.get_name()).as_slice());
+    }
+
+    let protocols: Vec<String> = class.copy_protocol_list().iter().map(|p| p.get_name()).collect();
+    if !protocols.is_empty() {
+        header.push_str(format!(" <{}>", protocols.connect(", ")).as_slice());
+    }
+    header.push_str("\n");
+
+    let ivars = class.copy_ivar_list();
+    if !ivars.is_empty() {
+        header.push_str("{\n");
+        for &ivar in ivars.iter() {
+            header.push_str(format!("    {};\n", ivar_declaration(ivar)).as_slice());
+        }
+        header.push_str("}\n");
+    }
+
+    let properties = class.copy_property_list();
+    if !properties.is_empty() {
+        header.push_str("\n");
+        for &property in properties.iter() {
+            header.push_str(format!("{};\n", property_declaration(property)).as_slice());
+        }
+    }
+
+    let class_methods = class.get_meta_class().copy_method_list();
+    let instance_methods = class.copy_method_list();
+    if !class_methods.is_empty() || !instance_methods.is_empty() {
+        header.push_str("\n");
+        for &method in class_methods.iter() {
+            header.push_str(format!("+ {};\n", method_declaration(method)).as_slice());
+        }
+        for &method in instance_methods.iter() {
+            header.push_str(format!("- {};\n", method_declaration(method)).as_slice());
+        }
+    }
+
+    header.push_str("\n@end\n");
+    header
+}
+
+/// Returns the declaration of an instance variable, such as `int _count`.
+pub unsafe fn ivar_declaration(ivar: InstanceVariable) -> String {
+    let name = ivar.get_name();
+    match encoding::parse(ivar.get_type_encoding().as_slice()) {
+        Some(ty) => ty.c_declaration(name.as_slice()),
+        None => format!("/* {} */ void *{}", ivar.get_type_encoding(), name),
+    }
+}
+
+/// Returns the declaration of a property, such as
+/// `@property (nonatomic, copy) NSString *name`.
+pub unsafe fn property_declaration(property: Property) -> String {
+    let name = property.get_name();
+    let mut ty = None;
+    let mut attributes = Vec::new();
+    let mut atomic = true;
+    for attribute in property.get_attributes().as_slice().split(',') {
+        match attribute.slice_shift_char() {
+            Some(('T', encoding)) => ty = encoding::parse(encoding),
+            Some(('R', _)) => attributes.push("readonly".to_string()),
+            Some(('C', _)) => attributes.push("copy".to_string()),
+            Some(('&', _)) => attributes.push("retain".to_string()),
+            Some(('W', _)) => attributes.push("weak".to_string()),
+            Some(('N', _)) => atomic = false,
+            Some(('G', getter)) => attributes.push(format!("getter={}", getter)),
+            Some(('S', setter)) => attributes.push(format!("setter={}", setter)),
+            _ => {}
+        }
+    }
+    if !atomic {
+        attributes.insert(0, "nonatomic".to_string());
+    }
+    let attributes = if attributes.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", attributes.connect(", "))
+    };
+    let declaration = match ty {
+        Some(ty) => ty.c_declaration(name.as_slice()),
+        None => format!("id {}", name),
+    };
+    format!("@property{} {}", attributes, declaration)
+}
+
+/// Returns a method declaration without the leading `+` or `-`, such as
+/// `(void)setFoo:(id)arg1 withBar:(int)arg2`.
+pub unsafe fn method_declaration(method: Method) -> String {
+    let types = method.get_type_encoding();
+    selector_declaration(method.get_name().as_slice(), types.as_str().unwrap_or(""))
+}
+
+/// Returns a method declaration for the selector `name` with the method type
+/// encoding `types`. Unparseable encodings are declared in terms of `id`.
+pub fn selector_declaration(name: &str, types: &str) -> String {
+    let (ret, args) = match encoding::parse_method(types) {
+        // Skip the implicit `self` and `_cmd` arguments.
+        Some(sig) => (sig.ret, sig.args.into_iter().skip(2).collect()),
+        None => (Encoding::Object(None), Vec::new()),
+    };
+
+    let mut declaration = format!("({})", ret.c_type());
+    if !name.contains_char(':') {
+        declaration.push_str(name);
+        return declaration;
+    }
+    let parts: Vec<&str> = name.split(':').collect();
+    for (i, part) in parts.init().iter().enumerate() {
+        if i > 0 { declaration.push_str(" "); }
+        let ty = match args.as_slice().get(i) {
+            Some(ty) => ty.c_type(),
+            None => "id".to_string(),
+        };
+        declaration.push_str(format!("{}:({})arg{}", part, ty, i + 1).as_slice());
+    }
+    declaration
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dump_class, method_declaration};
+    use {class, selector, Method};
+
+    // Required for testing `NSString`.
+    #[link(name = "Foundation", kind = "framework")]
+    extern {}
+
+    #[test]
+    pub fn test_dump_class() {
+        unsafe {
+            let header = dump_class(class("NSString"));
+            assert!(header.as_slice().starts_with("@interface NSString : NSObject <"));
+            assert!(header.as_slice().contains("- (unsigned long long)length;\n"));
+            assert!(header.as_slice().ends_with("@end\n"));
+        }
+    }
+
+    #[test]
+    pub fn test_method_declaration() {
+        unsafe {
+            let method = Method {
+                raw: class("NSObject").get_instance_method(selector("performSelector:withObject:")),
+            };
+            assert_eq!(method_declaration(method).as_slice(),
+                       "(id)performSelector:(SEL)arg1 withObject:(id)arg2");
+        }
+    }
+}
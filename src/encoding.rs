@@ -0,0 +1,384 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Parsing of Objective-C type encodings.
+//!
+//! See the [Type Encodings](https://developer.apple.com/library/mac/documentation/Cocoa/Conceptual/ObjCRuntimeGuide/Articles/ocrtTypeEncodings.html)
+//! section of the runtime programming guide.
+
+use std::fmt;
+
+/// A parsed type encoding.
+#[deriving(Clone, PartialEq, Eq)]
+pub enum Encoding {
+    Char,
+    Short,
+    Int,
+    Long,
+    LongLong,
+    UChar,
+    UShort,
+    UInt,
+    ULong,
+    ULongLong,
+    Float,
+    Double,
+    Bool,
+    Void,
+    /// `char *`
+    String,
+    /// `id`, with the static class name if the encoding records one.
+    Object(Option<String>),
+    /// A block object, encoded as `@?`.
+    Block,
+    Class,
+    Sel,
+    /// An unknown type, usually a function pointer's pointee.
+    Unknown,
+    /// A bit field of the given width.
+    BitField(uint),
+    Pointer(Box<Encoding>),
+    Array(uint, Box<Encoding>),
+    /// A struct, with its fields if the encoding includes them.
+    Struct(String, Vec<Encoding>),
+    /// A union, with its members if the encoding includes them.
+    Union(String, Vec<Encoding>),
+    /// A `const` qualified type.
+    Const(Box<Encoding>),
+}
+
+/// The parsed type encoding of a method.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct MethodEncoding {
+    pub ret: Encoding,
+    /// The argument types, including the receiver and the selector.
+    pub args: Vec<Encoding>,
+}
+
+/// Parses a single type encoding, such as an instance variable's.
+pub fn parse(encoding: &str) -> Option<Encoding> {
+    let mut parser = Parser { s: encoding.as_bytes(), pos: 0 };
+    let ty = parser.parse();
+    if parser.pos == parser.s.len() { ty } else { None }
+}
+
+/// Parses a method type encoding, such as `v16@0:8`. The stack offsets
+/// following each type are skipped.
+pub fn parse_method(encoding: &str) -> Option<MethodEncoding> {
+    let mut parser = Parser { s: encoding.as_bytes(), pos: 0 };
+    let ret = match parser.parse() {
+        Some(ret) => ret,
+        None => return None,
+    };
+    parser.skip_offset();
+    let mut args = Vec::new();
+    while parser.pos < parser.s.len() {
+        match parser.parse() {
+            Some(arg) => args.push(arg),
+            None => return None,
+        }
+        parser.skip_offset();
+    }
+    Some(MethodEncoding { ret: ret, args: args })
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: uint,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).map(|&c| c)
+    }
+
+    fn bump(&mut self) -> Option<u8> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn number(&mut self) -> Option<uint> {
+        let start = self.pos;
+        while self.peek().map_or(false, |c| (c as char).is_digit(10)) {
+            self.pos += 1;
+        }
+        ::std::str::from_utf8(self.s[start..self.pos]).and_then(|n| from_str(n))
+    }
+
+    fn skip_offset(&mut self) {
+        if self.peek() == Some(b'-') { self.pos += 1; }
+        self.number();
+    }
+
+    /// Reads up to, and consumes, `end`.
+    fn until(&mut self, end: u8) -> Option<String> {
+        let start = self.pos;
+        loop {
+            match self.bump() {
+                Some(c) if c == end => break,
+                Some(_) => {}
+                None => return None,
+            }
+        }
+        ::std::str::from_utf8(self.s[start..self.pos - 1]).map(|s| s.to_string())
+    }
+
+    fn parse(&mut self) -> Option<Encoding> {
+        let ty = match self.bump() {
+            Some(b'c') => Encoding::Char,
+            Some(b's') => Encoding::Short,
+            Some(b'i') => Encoding::Int,
+            Some(b'l') => Encoding::Long,
+            Some(b'q') => Encoding::LongLong,
+            Some(b'C') => Encoding::UChar,
+            Some(b'S') => Encoding::UShort,
+            Some(b'I') => Encoding::UInt,
+            Some(b'L') => Encoding::ULong,
+            Some(b'Q') => Encoding::ULongLong,
+            Some(b'f') => Encoding::Float,
+            Some(b'd') => Encoding::Double,
+            Some(b'B') => Encoding::Bool,
+            Some(b'v') => Encoding::Void,
+            Some(b'*') => Encoding::String,
+            Some(b'#') => Encoding::Class,
+            Some(b':') => Encoding::Sel,
+            Some(b'?') => Encoding::Unknown,
+            Some(b'@') => match self.peek() {
+                Some(b'?') => {
+                    self.pos += 1;
+                    // Extended block signatures, e.g. `@?<v@?@>`.
+                    if self.peek() == Some(b'<') {
+                        self.pos += 1;
+                        if self.until(b'>').is_none() { return None; }
+                    }
+                    Encoding::Block
+                }
+                Some(b'"') => {
+                    self.pos += 1;
+                    match self.until(b'"') {
+                        Some(name) => Encoding::Object(Some(name)),
+                        None => return None,
+                    }
+                }
+                _ => Encoding::Object(None),
+            },
+            Some(b'b') => match self.number() {
+                Some(width) => Encoding::BitField(width),
+                None => return None,
+            },
+            Some(b'^') => match self.parse() {
+                Some(ty) => Encoding::Pointer(box ty),
+                None => return None,
+            },
+            Some(b'r') => match self.parse() {
+                Some(ty) => Encoding::Const(box ty),
+                None => return None,
+            },
+            // Distributed Objects qualifiers carry no type information.
+            Some(b'n') | Some(b'N') | Some(b'o') | Some(b'O') | Some(b'R') | Some(b'V') => {
+                return self.parse();
+            }
+            Some(b'[') => {
+                let len = match self.number() {
+                    Some(len) => len,
+                    None => return None,
+                };
+                let ty = match self.parse() {
+                    Some(ty) => ty,
+                    None => return None,
+                };
+                if self.bump() != Some(b']') { return None; }
+                Encoding::Array(len, box ty)
+            }
+            Some(b'{') => match self.aggregate(b'}') {
+                Some((name, fields)) => Encoding::Struct(name, fields),
+                None => return None,
+            },
+            Some(b'(') => match self.aggregate(b')') {
+                Some((name, fields)) => Encoding::Union(name, fields),
+                None => return None,
+            },
+            _ => return None,
+        };
+        Some(ty)
+    }
+
+    fn aggregate(&mut self, end: u8) -> Option<(String, Vec<Encoding>)> {
+        let start = self.pos;
+        while self.peek().map_or(false, |c| c != b'=' && c != end) {
+            self.pos += 1;
+        }
+        let name = match ::std::str::from_utf8(self.s[start..self.pos]) {
+            Some(name) => name.to_string(),
+            None => return None,
+        };
+        let mut fields = Vec::new();
+        if self.peek() == Some(b'=') {
+            self.pos += 1;
+            while self.peek().map_or(false, |c| c != end) {
+                // Skip field names, as in `{CGPoint="x"d"y"d}`.
+                if self.peek() == Some(b'"') {
+                    self.pos += 1;
+                    if self.until(b'"').is_none() { return None; }
+                }
+                match self.parse() {
+                    Some(field) => fields.push(field),
+                    None => return None,
+                }
+            }
+        }
+        if self.bump() != Some(end) { return None; }
+        Some((name, fields))
+    }
+}
+
+impl Encoding {
+    /// Strips any `const` qualifiers.
+    pub fn unqualified(&self) -> &Encoding {
+        match *self {
+            Encoding::Const(ref ty) => ty.unqualified(),
+            ref ty => ty,
+        }
+    }
+
+    /// Returns the C spelling of the type, as used in a declaration without
+    /// a name.
+    pub fn c_type(&self) -> String {
+        self.c_declaration("").as_slice().trim_right().to_string()
+    }
+
+    /// Returns a C declaration of `name` with this type, such as
+    /// `int values[4]` or `unsigned int flag : 1`.
+    pub fn c_declaration(&self, name: &str) -> String {
+        let spaced = |ty: &str| {
+            if name.is_empty() { ty.to_string() } else { format!("{} {}", ty, name) }
+        };
+        match *self {
+            Encoding::Char => spaced("char"),
+            Encoding::Short => spaced("short"),
+            Encoding::Int => spaced("int"),
+            Encoding::Long => spaced("long"),
+            Encoding::LongLong => spaced("long long"),
+            Encoding::UChar => spaced("unsigned char"),
+            Encoding::UShort => spaced("unsigned short"),
+            Encoding::UInt => spaced("unsigned int"),
+            Encoding::ULong => spaced("unsigned long"),
+            Encoding::ULongLong => spaced("unsigned long long"),
+            Encoding::Float => spaced("float"),
+            Encoding::Double => spaced("double"),
+            Encoding::Bool => spaced("BOOL"),
+            Encoding::Void => spaced("void"),
+            Encoding::String => format!("char *{}", name),
+            Encoding::Object(Some(ref class)) if class.as_slice().starts_with("<") => {
+                spaced(format!("id{}", class).as_slice())
+            }
+            Encoding::Object(Some(ref class)) => format!("{} *{}", class, name),
+            Encoding::Object(None) => spaced("id"),
+            Encoding::Block => format!("void (^{})(void)", name),
+            Encoding::Class => spaced("Class"),
+            Encoding::Sel => spaced("SEL"),
+            Encoding::Unknown => spaced("void"),
+            Encoding::BitField(width) => format!("unsigned int {} : {}", name, width),
+            Encoding::Pointer(box Encoding::Unknown) => format!("void (*{})(void)", name),
+            Encoding::Pointer(ref ty) => match *ty.unqualified() {
+                // The pointer binds tighter than the array: `int (*p)[4]`.
+                Encoding::Array(..) => ty.c_declaration(format!("(*{})", name).as_slice()),
+                _ => ty.c_declaration(format!("*{}", name).as_slice()),
+            },
+            Encoding::Array(len, ref ty) => ty.c_declaration(format!("{}[{}]", name, len).as_slice()),
+            Encoding::Struct(ref tag, ref fields) if tag.as_slice() == "?" => {
+                spaced(anonymous_type("struct", fields.as_slice()).as_slice())
+            }
+            Encoding::Struct(ref tag, _) => spaced(format!("struct {}", tag).as_slice()),
+            Encoding::Union(ref tag, ref fields) if tag.as_slice() == "?" => {
+                spaced(anonymous_type("union", fields.as_slice()).as_slice())
+            }
+            Encoding::Union(ref tag, _) => spaced(format!("union {}", tag).as_slice()),
+            Encoding::Const(ref ty) => format!("const {}", ty.c_declaration(name)),
+        }
+    }
+}
+
+/// Spells out an anonymous struct or union, such as
+/// `struct { int x0; int x1; }`. The encoding does not include the names of
+/// the fields, so they are numbered, as class-dump does.
+fn anonymous_type(keyword: &str, fields: &[Encoding]) -> String {
+    let mut out = format!("{} {{ ", keyword);
+    for (i, field) in fields.iter().enumerate() {
+        out.push_str(format!("{}; ", field.c_declaration(format!("x{}", i).as_slice())).as_slice());
+    }
+    out.push_str("}");
+    out
+}
+
+impl fmt::Show for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.c_type())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Encoding, MethodEncoding, parse, parse_method};
+
+    #[test]
+    pub fn test_parse_scalars() {
+        assert_eq!(parse("i"), Some(Encoding::Int));
+        assert_eq!(parse("Q"), Some(Encoding::ULongLong));
+        assert_eq!(parse(":"), Some(Encoding::Sel));
+        assert_eq!(parse("ii"), None);
+        assert_eq!(parse("x"), None);
+    }
+
+    #[test]
+    pub fn test_parse_compound() {
+        assert_eq!(parse("^{CGPoint=dd}"),
+                   Some(Encoding::Pointer(box Encoding::Struct("CGPoint".to_string(),
+                                                               vec![Encoding::Double, Encoding::Double]))));
+        assert_eq!(parse("{CGPoint=\"x\"d\"y\"d}"),
+                   Some(Encoding::Struct("CGPoint".to_string(), vec![Encoding::Double, Encoding::Double])));
+        assert_eq!(parse("[4i]"), Some(Encoding::Array(4, box Encoding::Int)));
+        assert_eq!(parse("@\"NSString\""), Some(Encoding::Object(Some("NSString".to_string()))));
+        assert_eq!(parse("@?<v@?@>"), Some(Encoding::Block));
+        assert_eq!(parse("r*"), Some(Encoding::Const(box Encoding::String)));
+        assert_eq!(parse("{__CFString}"), Some(Encoding::Struct("__CFString".to_string(), vec![])));
+    }
+
+    #[test]
+    pub fn test_parse_method() {
+        assert_eq!(parse_method("v16@0:8"),
+                   Some(MethodEncoding { ret: Encoding::Void, args: vec![Encoding::Object(None), Encoding::Sel] }));
+        assert_eq!(parse_method("@24@0:8Vr^i16"),
+                   Some(MethodEncoding {
+                       ret: Encoding::Object(None),
+                       args: vec![Encoding::Object(None), Encoding::Sel,
+                                  Encoding::Const(box Encoding::Pointer(box Encoding::Int))],
+                   }));
+    }
+
+    #[test]
+    pub fn test_c_declaration() {
+        assert_eq!(parse("[4i]").unwrap().c_declaration("values").as_slice(), "int values[4]");
+        assert_eq!(parse("^{CGPoint=dd}").unwrap().c_declaration("p").as_slice(), "struct CGPoint *p");
+        assert_eq!(parse("@\"NSString\"").unwrap().c_declaration("name").as_slice(), "NSString *name");
+        assert_eq!(parse("b1").unwrap().c_declaration("flag").as_slice(), "unsigned int flag : 1");
+        assert_eq!(parse("^?").unwrap().c_type().as_slice(), "void (*)(void)");
+        assert_eq!(parse("r*").unwrap().c_type().as_slice(), "const char *");
+        assert_eq!(parse("^[4i]").unwrap().c_declaration("values").as_slice(), "int (*values)[4]");
+        assert_eq!(parse("^[4i]").unwrap().c_type().as_slice(), "int (*)[4]");
+        assert_eq!(parse("{?=ii}").unwrap().c_declaration("pair").as_slice(), "struct { int x0; int x1; } pair");
+        assert_eq!(parse("(?=if)").unwrap().c_type().as_slice(), "union { int x0; float x1; }");
+    }
+}
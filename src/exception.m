@@ -0,0 +1,29 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#import <Foundation/Foundation.h>
+
+// Calls `f(context)`, catching any Objective-C exception it throws. Returns 0
+// if `f` returned normally, or 1 if an exception was caught, in which case the
+// exception is retained and stored in `exception`.
+int objc_rs_try(void (*f)(void *), void *context, id *exception) {
+    @try {
+        f(context);
+        return 0;
+    }
+    @catch (id e) {
+        *exception = [e retain];
+        return 1;
+    }
+}
@@ -0,0 +1,407 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Objective-C exceptions.
+//!
+//! An exception unwinding through Rust frames is undefined behaviour, so any
+//! message send that may throw should be wrapped in `catch_exception`:
+//!
+//! ~~~rust
+//! let result = catch_exception(|| {
+//!     msg_send::<Id>()(array, selector("objectAtIndex:"), 10u)
+//! });
+//! match result {
+//!     Ok(object) => ...,
+//!     Err(exception) => println!("caught {}", exception),
+//! }
+//! ~~~
+//!
+//! Exceptions can be thrown to Objective-C callers with `throw`:
+//!
+//! ~~~rust
+//! throw(Exception::new("RSInvalidArgumentException", Some("negative length"), Id::nil()));
+//! ~~~
+//!
+//! Likewise, a Rust panic must not unwind into Objective-C. Method
+//! implementations added with `Class::add_method_fn` are run under
+//! `guard_panic`, which turns a panic into a thrown `RustPanic` exception,
+//! or aborts the process, depending on the `PanicStrategy`.
+
+use libc::{c_char, c_int, c_void};
+use std::any::{Any, AnyRefExt};
+use std::fmt;
+use std::io::stderr;
+use std::mem;
+use std::rt::unwind;
+use std::sync::{Arc, MUTEX_INIT, StaticMutex};
+use std::sync::atomic::{AtomicBool, INIT_ATOMIC_BOOL, SeqCst};
+
+use {Id, class, ffi, msg_send, selector};
+
+#[link(name = "objc_rs_exception", kind = "static")]
+extern {
+    fn objc_rs_try(f: extern fn(*mut c_void), context: *mut c_void, exception: *mut Id) -> c_int;
+}
+
+#[link(name = "Foundation", kind = "framework")]
+extern {}
+
+/// An Objective-C exception, caught or to be thrown. The exception object is
+/// retained for the lifetime of the `Exception`.
+pub struct Exception {
+    raw: Id,
+}
+
+impl Exception {
+    /// Creates an `NSException` with the given name, reason and `userInfo`
+    /// dictionary, which may be nil.
+    pub unsafe fn new(name: &str, reason: Option<&str>, user_info: Id) -> Exception {
+        let reason = match reason {
+            Some(reason) => nsstring(reason),
+            None => Id::nil(),
+        };
+        let exception: Id = msg_send()(class("NSException").as_id(), selector("exceptionWithName:reason:userInfo:"),
+                                       nsstring(name), reason, user_info);
+        Exception::from_unretained(exception)
+    }
+
+    /// Takes ownership of a retained exception object.
+    pub unsafe fn from_retained(raw: Id) -> Exception {
+        Exception { raw: raw }
+    }
+
+    /// Retains an exception object.
+    pub unsafe fn from_unretained(raw: Id) -> Exception {
+        Exception { raw: msg_send()(raw, selector("retain")) }
+    }
+
+    /// The exception object. This is usually an `NSException`, but any
+    /// object may be thrown.
+    #[inline]
+    pub fn as_id(&self) -> Id {
+        self.raw
+    }
+
+    /// Releases ownership of the exception object, returning it retained.
+    pub fn into_id(self) -> Id {
+        let raw = self.raw;
+        unsafe { mem::forget(self); }
+        raw
+    }
+
+    /// The `name` of an `NSException`, or `None` for other objects.
+    pub fn name(&self) -> Option<String> {
+        unsafe { self.string_property("name") }
+    }
+
+    /// The `reason` of an `NSException`, or `None` for other objects or if
+    /// no reason was given.
+    pub fn reason(&self) -> Option<String> {
+        unsafe { self.string_property("reason") }
+    }
+
+    unsafe fn string_property(&self, name: &str) -> Option<String> {
+        let sel = selector(name);
+        let responds: i8 = msg_send()(self.raw, selector("respondsToSelector:"), sel);
+        if responds == 0 {
+            return None;
+        }
+        let string: Id = msg_send()(self.raw, sel);
+        if string == Id::nil() {
+            return None;
+        }
+        let bytes: *const c_char = msg_send()(string, selector("UTF8String"));
+        Some(String::from_raw_buf(bytes as *const u8))
+    }
+}
+
+impl Drop for Exception {
+    fn drop(&mut self) {
+        unsafe { msg_send::<()>()(self.raw, selector("release")); }
+    }
+}
+
+impl fmt::Show for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.name(), self.reason()) {
+            (Some(name), Some(reason)) => write!(f, "{}: {}", name, reason),
+            (Some(name), None) => write!(f, "{}", name),
+            _ => write!(f, "{}", self.raw),
+        }
+    }
+}
+
+extern fn try_callback<F: FnOnce()>(context: *mut c_void) {
+    let closure = unsafe { &mut *(context as *mut Option<F>) };
+    (closure.take().unwrap())();
+}
+
+fn try_no_ret<F: FnOnce()>(closure: F) -> Result<(), Exception> {
+    let mut closure = Some(closure);
+    let mut exception = Id::nil();
+    let caught = unsafe {
+        objc_rs_try(try_callback::<F>, &mut closure as *mut Option<F> as *mut c_void, &mut exception)
+    };
+    if caught == 0 {
+        Ok(())
+    } else {
+        Err(Exception { raw: exception })
+    }
+}
+
+/// Calls `closure`, catching any Objective-C exception thrown while it runs.
+///
+/// This is unsafe because `closure` must not panic: the panic would unwind
+/// through the Objective-C frames of the `@try` block. It cannot be caught
+/// on the way, since Rust's unwinding catches Objective-C exceptions too.
+pub unsafe fn catch_exception<R, F: FnOnce() -> R>(closure: F) -> Result<R, Exception> {
+    let mut value = None;
+    {
+        let value_ref = &mut value;
+        try!(try_no_ret(move |:| { *value_ref = Some(closure()); }));
+    }
+    Ok(value.unwrap())
+}
+
+/// What to do when a Rust method implementation panics.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum PanicStrategy {
+    /// Throw an `NSException` named `RustPanic`, with the panic message as
+    /// its reason. This is the default.
+    Throw,
+    /// Print the panic message and abort the process.
+    Abort,
+}
+
+static ABORT_ON_PANIC: AtomicBool = INIT_ATOMIC_BOOL;
+
+/// Sets what `guard_panic` does with a panic, for every thread.
+pub fn set_panic_strategy(strategy: PanicStrategy) {
+    ABORT_ON_PANIC.store(strategy == PanicStrategy::Abort, SeqCst);
+}
+
+/// Returns what `guard_panic` does with a panic.
+pub fn panic_strategy() -> PanicStrategy {
+    if ABORT_ON_PANIC.load(SeqCst) { PanicStrategy::Abort } else { PanicStrategy::Throw }
+}
+
+/// Calls `closure`, stopping any panic from unwinding out of it.
+///
+/// This is intended for code called by Objective-C. A panic is handled
+/// according to the current `PanicStrategy`.
+pub unsafe fn guard_panic<R, F: FnOnce() -> R>(closure: F) -> R {
+    let exception = match catch_panic(closure) {
+        Ok(value) => return value,
+        Err(exception) => exception,
+    };
+    // The exception unwinds through this frame, which has nothing left to
+    // drop.
+    throw_object(exception)
+}
+
+/// Calls `closure`, returning the autoreleased exception to throw if it
+/// panicked. The panic's cause and message are dropped before returning.
+unsafe fn catch_panic<R, F: FnOnce() -> R>(closure: F) -> Result<R, Id> {
+    let mut closure = Some(closure);
+    let mut value = None;
+    let cause = match unwind::try(|| { value = Some((closure.take().unwrap())()); }) {
+        Ok(()) => return Ok(value.unwrap()),
+        Err(cause) => cause,
+    };
+    let message = panic_message(&*cause);
+    drop(cause);
+    match panic_strategy() {
+        PanicStrategy::Throw => {
+            let exception = Exception::new("RustPanic", Some(message.as_slice()), Id::nil());
+            Err(autorelease(exception))
+        }
+        PanicStrategy::Abort => {
+            let _ = writeln!(&mut stderr(), "fatal runtime error: panic in an Objective-C method: {}", message);
+            ::libc::abort()
+        }
+    }
+}
+
+/// Returns the message a panic was started with.
+fn panic_message(cause: &Any) -> String {
+    match cause.downcast_ref::<&'static str>() {
+        Some(message) => message.to_string(),
+        None => match cause.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "Box<Any>".to_string(),
+        },
+    }
+}
+
+/// Gives up ownership of `exception` to the autorelease pool.
+unsafe fn autorelease(exception: Exception) -> Id {
+    msg_send()(exception.into_id(), selector("autorelease"))
+}
+
+/// Returns an autoreleased `NSString` copy of `s`.
+unsafe fn nsstring(s: &str) -> Id {
+    msg_send()(class("NSString").as_id(), selector("stringWithUTF8String:"), s.to_c_str().as_ptr())
+}
+
+/// Throws `exception`, to be caught by an Objective-C `@catch` or
+/// `catch_exception`.
+///
+/// Only Objective-C frames, and Rust frames with nothing to drop, may lie
+/// between the throw and the catch.
+pub unsafe fn throw(exception: Exception) -> ! {
+    throw_object(autorelease(exception))
+}
+
+/// Throws an arbitrary object, as `@throw object` would.
+pub unsafe fn throw_object(object: Id) -> ! {
+    ffi::objc_exception_throw(object.raw);
+    unreachable!()
+}
+
+/// A function called with an exception that nothing caught, just before the
+/// process terminates.
+pub type UncaughtExceptionHandler = extern "C" fn(Id);
+
+/// Sets the runtime's uncaught exception handler, returning the previous
+/// one.
+pub unsafe fn set_uncaught_exception_handler(handler: Option<UncaughtExceptionHandler>)
+                                             -> Option<UncaughtExceptionHandler> {
+    mem::transmute(ffi::objc_setUncaughtExceptionHandler(mem::transmute(handler)))
+}
+
+type Hook = Box<Fn(&Exception) + Send + Sync>;
+
+static HOOK_LOCK: StaticMutex = MUTEX_INIT;
+static mut HOOK: *mut Arc<Hook> = 0 as *mut Arc<Hook>;
+
+/// Calls `hook` with any exception that nothing caught, before the process
+/// terminates, replacing the previous hook. Panics in the hook are handled
+/// as if the `PanicStrategy` were `Abort`.
+pub fn set_uncaught_exception_hook(hook: Box<Fn(&Exception) + Send + Sync>) {
+    unsafe {
+        let _guard = HOOK_LOCK.lock();
+        let old = HOOK;
+        HOOK = mem::transmute(box Arc::new(hook));
+        if !old.is_null() {
+            drop(mem::transmute::<_, Box<Arc<Hook>>>(old));
+        }
+        set_uncaught_exception_handler(Some(call_uncaught_exception_hook));
+    }
+}
+
+extern fn call_uncaught_exception_hook(exception: Id) {
+    unsafe {
+        // The hook is called without the lock held, so that it may replace
+        // itself or throw another exception.
+        let hook = {
+            let _guard = HOOK_LOCK.lock();
+            if HOOK.is_null() {
+                return;
+            }
+            (*HOOK).clone()
+        };
+        let exception = Exception::from_unretained(exception);
+        match unwind::try(|| (**hook)(&exception)) {
+            Ok(()) => {}
+            Err(cause) => {
+                let _ = writeln!(&mut stderr(), "fatal runtime error: panic in the uncaught exception hook: {}",
+                                 panic_message(&*cause));
+                ::libc::abort()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{Exception, call_uncaught_exception_hook, catch_exception, set_uncaught_exception_handler};
+    use super::{set_uncaught_exception_hook, throw};
+    use {Id, class, msg_send, selector};
+    use NSClassObject;
+
+    #[test]
+    pub fn test_no_exception() {
+        unsafe {
+            assert_eq!(catch_exception(|| 5i).ok(), Some(5i));
+        }
+    }
+
+    #[test]
+    pub fn test_catch_exception() {
+        unsafe {
+            let array: Id = msg_send()(class("NSArray").as_id(), selector("array"));
+            let exception = catch_exception(|| {
+                msg_send::<Id>()(array, selector("objectAtIndex:"), 0u)
+            }).err().unwrap();
+            assert_eq!(exception.name(), Some("NSRangeException".to_string()));
+            assert!(exception.reason().is_some());
+            assert!(exception.to_string().as_slice().starts_with("NSRangeException: "));
+        }
+    }
+
+    #[test]
+    pub fn test_throw() {
+        unsafe {
+            let exception = catch_exception(|| {
+                throw(Exception::new("RSTestException", Some("because"), Id::nil()));
+            }).err().unwrap();
+            assert_eq!(exception.name(), Some("RSTestException".to_string()));
+            assert_eq!(exception.reason(), Some("because".to_string()));
+
+            let exception = catch_exception(|| {
+                throw(Exception::new("RSTestException", None, Id::nil()));
+            }).err().unwrap();
+            assert_eq!(exception.reason(), None);
+        }
+    }
+
+    #[test]
+    pub fn test_panicking_method() {
+        unsafe {
+            let test_class = class("NSObject").allocate_unique_class_pair("RSPanickingMethodTest", 0);
+            assert!(test_class.add_method_fn(selector("explode"), "v@:", |&: _this: Id| {
+                panic!("kaboom")
+            }));
+            test_class.register_class_pair();
+
+            let object = NSClassObject::c_new(test_class);
+            let exception = catch_exception(|| {
+                msg_send::<()>()(object, selector("explode"))
+            }).err().unwrap();
+            assert_eq!(exception.name(), Some("RustPanic".to_string()));
+            assert_eq!(exception.reason(), Some("kaboom".to_string()));
+        }
+    }
+
+    #[test]
+    pub fn test_uncaught_exception_hook() {
+        unsafe {
+            let names = Arc::new(Mutex::new(Vec::new()));
+            let hook_names = names.clone();
+            set_uncaught_exception_hook(box move |&: exception: &Exception| {
+                hook_names.lock().push(exception.name().unwrap());
+                // Replacing the hook from inside it must not deadlock.
+                set_uncaught_exception_hook(box |&: _exception: &Exception| {});
+            });
+
+            let exception = Exception::new("RSUncaughtException", None, Id::nil());
+            call_uncaught_exception_hook(exception.as_id());
+            call_uncaught_exception_hook(exception.as_id());
+            assert_eq!(*names.lock(), vec!["RSUncaughtException".to_string()]);
+            set_uncaught_exception_handler(None);
+        }
+    }
+}
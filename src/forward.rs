@@ -0,0 +1,307 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Message forwarding.
+//!
+//! A message that an object has no method for, and that is not resolved
+//! dynamically, is forwarded. There are two paths:
+//!
+//! - The fast path, `set_forwarding_target`, redirects the message unchanged
+//!   to another object.
+//! - The slow path, `set_invocation_handler`, packages the message as an
+//!   `NSInvocation`, whose arguments and return value a Rust handler reads
+//!   and writes through `Invocation`. The handler also provides the type
+//!   encodings of the messages it handles, as the runtime needs them to
+//!   build the invocation.
+//!
+//! ~~~rust
+//! set_invocation_handler(proxy_class,
+//!     |&: _this: Id, name: Selector| {
+//!         if name == selector("add:to:") { Some("i@:ii".to_string()) } else { None }
+//!     },
+//!     |&: _this: Id, invocation: Invocation| {
+//!         let a: c_int = invocation.argument(2);
+//!         let b: c_int = invocation.argument(3);
+//!         invocation.set_return_value(a + b);
+//!         true
+//!     });
+//! ~~~
+
+use libc::c_char;
+use std::mem;
+
+use {Class, Id, Selector, selector};
+
+#[link(name = "Foundation", kind = "framework")]
+extern {
+    fn NSGetSizeAndAlignment(types: *const c_char, size: *mut uint, alignment: *mut uint) -> *const c_char;
+}
+
+/// Sets the closure that chooses another object to receive messages that
+/// instances of `class` have no method for, by overriding
+/// `-forwardingTargetForSelector:`.
+///
+/// If the closure returns nil, the superclass's implementation is tried,
+/// and then the slow path.
+///
+/// Returns `false` if `class` already defines
+/// `-forwardingTargetForSelector:`.
+pub unsafe fn set_forwarding_target<F>(class: Class, target: F) -> bool
+        where F: Fn(Id, Selector) -> Id + 'static {
+    class.add_method_fn(selector("forwardingTargetForSelector:"), "@@::",
+                        move |&: this: Id, name: Selector| -> Id {
+        let target = target(this, name);
+        if target != Id::nil() {
+            target
+        } else {
+            msg_send_super!(class, this, "forwardingTargetForSelector:", name)
+        }
+    })
+}
+
+/// Sets the closures that handle messages that instances of `class` have no
+/// method for, by overriding `-methodSignatureForSelector:` and
+/// `-forwardInvocation:`.
+///
+/// `signature` returns the method type encoding of a message, or `None` to
+/// use the superclass's `-methodSignatureForSelector:`. `handler` returns
+/// `true` if it handled the invocation, or `false` to use the superclass's
+/// `-forwardInvocation:`, which raises an exception.
+///
+/// Returns `false` if `class` already defines either method.
+pub unsafe fn set_invocation_handler<S, F>(class: Class, signature: S, handler: F) -> bool
+        where S: Fn(Id, Selector) -> Option<String> + 'static,
+              F: Fn(Id, Invocation) -> bool + 'static {
+    // Check both methods first, so that `class` is never left with only one.
+    if defines_method(class, selector("methodSignatureForSelector:")) ||
+            defines_method(class, selector("forwardInvocation:")) {
+        return false;
+    }
+    let added = class.add_method_fn(selector("methodSignatureForSelector:"), "@@::",
+                                    move |&: this: Id, name: Selector| -> Id {
+        match signature(this, name) {
+            Some(types) => {
+                msg_send!(::class("NSMethodSignature").as_id(), "signatureWithObjCTypes:",
+                          types.to_c_str().as_ptr())
+            }
+            None => msg_send_super!(class, this, "methodSignatureForSelector:", name),
+        }
+    });
+    added && class.add_method_fn(selector("forwardInvocation:"), "v@:@",
+                                 move |&: this: Id, invocation: Id| {
+        if !handler(this, Invocation { raw: invocation }) {
+            let _: () = msg_send_super!(class, this, "forwardInvocation:", invocation);
+        }
+    })
+}
+
+/// Returns `true` if `class` itself, rather than a superclass, defines the
+/// instance method `name`.
+unsafe fn defines_method(class: Class, name: Selector) -> bool {
+    let method = class.get_instance_method(name);
+    let sup = class.get_super_class();
+    !method.is_null() && (sup == Class::nil() || sup.get_instance_method(name) != method)
+}
+
+/// An `NSInvocation`: a message, with its arguments and return value.
+///
+/// The arguments are numbered as in the method type encoding, so the
+/// receiver is at index 0, the selector at 1, and the first explicit
+/// argument at 2. The typed accessors panic if the size of the Rust type
+/// does not match that of the encoded type.
+pub struct Invocation {
+    pub raw: Id,
+}
+
+impl Invocation {
+    /// The selector of the message.
+    pub unsafe fn selector(self) -> Selector {
+        msg_send!(self.raw, "selector")
+    }
+
+    /// The receiver of the message.
+    pub unsafe fn target(self) -> Id {
+        msg_send!(self.raw, "target")
+    }
+
+    pub unsafe fn set_target(self, target: Id) {
+        msg_send!(self.raw, "setTarget:", target)
+    }
+
+    /// The `NSMethodSignature` describing the message.
+    pub unsafe fn method_signature(self) -> Id {
+        msg_send!(self.raw, "methodSignature")
+    }
+
+    /// The number of arguments, including the receiver and selector.
+    pub unsafe fn number_of_arguments(self) -> uint {
+        msg_send!(self.method_signature(), "numberOfArguments")
+    }
+
+    /// The type encoding of the argument at `index`.
+    pub unsafe fn argument_type(self, index: uint) -> String {
+        String::from_raw_buf(self.raw_argument_type(index) as *const u8)
+    }
+
+    /// The type encoding of the return value.
+    pub unsafe fn return_type(self) -> String {
+        String::from_raw_buf(self.raw_return_type() as *const u8)
+    }
+
+    /// Returns a copy of the bytes of the argument at `index`.
+    pub unsafe fn argument_bytes(self, index: uint) -> Vec<u8> {
+        let mut bytes = Vec::from_elem(type_size(self.raw_argument_type(index)), 0u8);
+        msg_send::<()>()(self.raw, selector("getArgument:atIndex:"), bytes.as_mut_ptr(), index as int);
+        bytes
+    }
+
+    /// Returns the argument at `index`.
+    pub unsafe fn argument<T>(self, index: uint) -> T {
+        check_size::<T>(self.raw_argument_type(index));
+        let mut value: T = mem::uninitialized();
+        msg_send::<()>()(self.raw, selector("getArgument:atIndex:"), &mut value as *mut T, index as int);
+        value
+    }
+
+    /// Replaces the argument at `index`.
+    pub unsafe fn set_argument<T>(self, index: uint, value: T) {
+        check_size::<T>(self.raw_argument_type(index));
+        msg_send::<()>()(self.raw, selector("setArgument:atIndex:"), &value as *const T, index as int);
+        mem::forget(value);
+    }
+
+    /// Returns the return value, which is only set once the invocation has
+    /// been invoked or `set_return_value` called.
+    pub unsafe fn return_value<T>(self) -> T {
+        check_size::<T>(self.raw_return_type());
+        let mut value: T = mem::uninitialized();
+        msg_send::<()>()(self.raw, selector("getReturnValue:"), &mut value as *mut T);
+        value
+    }
+
+    /// Sets the value returned to the sender of the message.
+    pub unsafe fn set_return_value<T>(self, value: T) {
+        check_size::<T>(self.raw_return_type());
+        msg_send::<()>()(self.raw, selector("setReturnValue:"), &value as *const T);
+        mem::forget(value);
+    }
+
+    /// Sends the message to `target`, storing the return value in the
+    /// invocation. This is how a handler forwards a message elsewhere.
+    pub unsafe fn invoke_with_target(self, target: Id) {
+        msg_send!(self.raw, "invokeWithTarget:", target)
+    }
+
+    unsafe fn raw_argument_type(self, index: uint) -> *const c_char {
+        let count = self.number_of_arguments();
+        if index >= count {
+            panic!("argument index {} out of range for {} arguments", index, count);
+        }
+        msg_send!(self.method_signature(), "getArgumentTypeAtIndex:", index)
+    }
+
+    unsafe fn raw_return_type(self) -> *const c_char {
+        msg_send!(self.method_signature(), "methodReturnType")
+    }
+}
+
+/// Returns the size of the type encoded by `types`.
+unsafe fn type_size(types: *const c_char) -> uint {
+    let mut size = 0;
+    let mut alignment = 0;
+    NSGetSizeAndAlignment(types, &mut size, &mut alignment);
+    size
+}
+
+/// Panics if `T` is not the size of the type encoded by `types`.
+unsafe fn check_size<T>(types: *const c_char) {
+    let size = type_size(types);
+    if size != mem::size_of::<T>() {
+        panic!("type of size {} does not match encoding `{}` of size {}", mem::size_of::<T>(),
+               String::from_raw_buf(types as *const u8), size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::c_int;
+
+    use exception::catch_exception;
+    use super::{Invocation, set_forwarding_target, set_invocation_handler};
+    use {Id, Selector, class, msg_send, selector};
+    use NSClassObject;
+
+    #[test]
+    pub fn test_forwarding_target() {
+        unsafe {
+            let string: Id = msg_send()(class("NSString").as_id(), selector("stringWithUTF8String:"),
+                                        "abc".to_c_str().as_ptr());
+            let test_class = class("NSObject").allocate_unique_class_pair("RSForwardingTargetTest", 0);
+            assert!(set_forwarding_target(test_class, move |&: _this: Id, name: Selector| {
+                if name == selector("length") { string } else { Id::nil() }
+            }));
+            test_class.register_class_pair();
+
+            let proxy = NSClassObject::c_new(test_class);
+            let length: uint = msg_send()(proxy, selector("length"));
+            assert_eq!(length, 3);
+        }
+    }
+
+    #[test]
+    pub fn test_invocation_handler() {
+        unsafe {
+            let test_class = class("NSObject").allocate_unique_class_pair("RSInvocationHandlerTest", 0);
+            assert!(set_invocation_handler(test_class,
+                |&: _this: Id, name: Selector| {
+                    if name == selector("add:to:") { Some("i@:ii".to_string()) } else { None }
+                },
+                |&: this: Id, invocation: Invocation| {
+                    assert_eq!(invocation.target(), this);
+                    if invocation.selector() != selector("add:to:") {
+                        return false;
+                    }
+                    assert_eq!(invocation.number_of_arguments(), 4);
+                    assert_eq!(invocation.return_type().as_slice(), "i");
+                    let a: c_int = invocation.argument(2);
+                    let b: c_int = invocation.argument(3);
+                    invocation.set_return_value(a + b);
+                    true
+                }));
+            test_class.register_class_pair();
+
+            let proxy = NSClassObject::c_new(test_class);
+            let sum: c_int = msg_send()(proxy, selector("add:to:"), 2 as c_int, 3 as c_int);
+            assert_eq!(sum, 5);
+
+            let exception = catch_exception(|| {
+                msg_send::<()>()(proxy, selector("missing"))
+            }).err().unwrap();
+            assert_eq!(exception.name(), Some("NSInvalidArgumentException".to_string()));
+        }
+    }
+
+    #[test]
+    pub fn test_invocation_handler_conflict() {
+        unsafe {
+            let test_class = class("NSObject").allocate_unique_class_pair("RSInvocationConflictTest", 0);
+            assert!(test_class.add_method_fn(selector("forwardInvocation:"), "v@:@", |&: _this: Id, _invocation: Id| {}));
+            assert!(!set_invocation_handler(test_class,
+                |&: _this: Id, _name: Selector| None,
+                |&: _this: Id, _invocation: Invocation| false));
+            assert_eq!(test_class.get_instance_method(selector("methodSignatureForSelector:")),
+                       class("NSObject").get_instance_method(selector("methodSignatureForSelector:")));
+        }
+    }
+}
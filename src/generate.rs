@@ -0,0 +1,340 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Generation of Rust wrappers that follow the conventions described in the
+//! README.
+//!
+//! For a class `NSFoo`, this emits a `NSFoo` type alias for `objc::Id`, a
+//! `NSClassFoo` unit struct implementing `objc::GetClass`, and associated
+//! functions for each method (`c_`/`i_` prefixed), property (`i_`) and
+//! instance variable (`iv_`). The generated code expects the `objc` and
+//! `libc` crates to be in scope.
+
+use std::collections::HashSet;
+
+use dump;
+use encoding;
+use encoding::Encoding;
+use metadata::{ClassInfo, IvarInfo, MethodInfo, PropertyInfo};
+use naming;
+use naming::MethodKind;
+
+/// Options controlling the generated code.
+pub struct Options {
+    /// The namespace prefix of the class, such as `NS`. If `None`, the run
+    /// of leading capitals (less the first letter of the identifier) is used.
+    pub namespace: Option<String>,
+}
+
+impl Options {
+    pub fn new() -> Options {
+        Options { namespace: None }
+    }
+}
+
+/// The namespace prefixes of Apple's frameworks.
+static KNOWN_NAMESPACES: &'static [&'static str] = &[
+    "AB", "AV", "CA", "CB", "CF", "CG", "CI", "CL", "CT", "GK", "MK", "NS", "QL", "SK", "UI", "WK",
+];
+
+/// Splits a class name into its namespace prefix and identifier, such as
+/// `("NS", "String")` for `NSString`.
+///
+/// A known prefix is split off whatever follows it, so `NSURLSession` is
+/// `("NS", "URLSession")`. Otherwise the prefix is the run of capitals
+/// before the first word, and is only split off if a lowercase letter
+/// follows the word's capital.
+pub fn split_namespace(name: &str) -> (&str, &str) {
+    let starts_word = |rest: &str| rest.chars().next().map_or(false, |c| c.is_uppercase());
+    for &namespace in KNOWN_NAMESPACES.iter() {
+        if name.starts_with(namespace) && starts_word(name[namespace.len()..]) {
+            return (name[..namespace.len()], name[namespace.len()..]);
+        }
+    }
+    let capitals = name.chars().take_while(|c| c.is_uppercase()).count();
+    let lowercase_follows = name[capitals..].chars().next().map_or(false, |c| c.is_lowercase());
+    let split = if capitals > 1 && lowercase_follows { capitals - 1 } else { 0 };
+    (name[..split], name[split..])
+}
+
+/// Generates the Rust wrapper for a class.
+pub fn generate(info: &ClassInfo, options: &Options) -> String {
+    let name = info.name.as_slice();
+    let (namespace, ident) = match options.namespace {
+        Some(ref namespace) if name.starts_with(namespace.as_slice()) => {
+            (namespace.as_slice(), name[namespace.len()..])
+        }
+        _ => split_namespace(name),
+    };
+    let unit = format!("{}Class{}", namespace, ident);
+
+    let mut out = String::new();
+    out.push_str(format!("pub type {} = objc::Id;\n\n", name).as_slice());
+    out.push_str(format!("pub struct {};\n\n", unit).as_slice());
+    out.push_str(format!("impl objc::GetClass for {} {{\n", unit).as_slice());
+    out.push_str("    #[inline]\n");
+    out.push_str(format!("    fn get_class_name(self) -> &'static str {{ \"{}\" }}\n", name).as_slice());
+    out.push_str("}\n\n");
+
+    out.push_str(format!("/// Class and instance methods for `{}`\n", name).as_slice());
+    out.push_str("#[allow(non_snake_case)]\n");
+    out.push_str(format!("impl {} {{\n", unit).as_slice());
+
+    let mut items = Vec::new();
+    let mut seen = HashSet::new();
+    for method in info.class_methods.iter() {
+        if seen.insert(method_ident(true, method.name.as_slice())) {
+            items.push(method_wrapper(true, method));
+        }
+    }
+    for method in info.instance_methods.iter() {
+        if seen.insert(method_ident(false, method.name.as_slice())) {
+            items.push(method_wrapper(false, method));
+        }
+    }
+    for property in info.properties.iter() {
+        for method in property_accessors(property).iter() {
+            if seen.insert(method_ident(false, method.name.as_slice())) {
+                items.push(method_wrapper(false, method));
+            }
+        }
+    }
+    for ivar in info.ivars.iter() {
+        items.push(ivar_accessor(ivar));
+    }
+    out.push_str(items.connect("\n").as_slice());
+    out.push_str("}\n");
+    out
+}
+
+fn method_ident(is_class: bool, selector: &str) -> String {
+    naming::method_ident(if is_class { MethodKind::Class } else { MethodKind::Instance }, selector)
+}
+
+fn method_wrapper(is_class: bool, method: &MethodInfo) -> String {
+    let selector = method.name.as_slice();
+    let sig = encoding::parse_method(method.types.as_slice());
+    let (ret, args) = match sig {
+        Some(sig) => (sig.ret, sig.args.into_iter().skip(2).collect()),
+        None => (Encoding::Object(None), Vec::new()),
+    };
+    let arity = selector.chars().filter(|&c| c == ':').count();
+
+    let mut params = vec![if is_class { "class: objc::Class".to_string() } else { "this: objc::Id".to_string() }];
+    let mut call_args = vec![
+        if is_class { "class.as_id()".to_string() } else { "this".to_string() },
+        format!("objc::selector(\"{}\")", selector),
+    ];
+    for i in range(0, arity) {
+        let ty = args.as_slice().get(i).map_or("objc::Id".to_string(), |ty| rust_type(ty));
+        params.push(format!("arg{}: {}", i + 1, ty));
+        call_args.push(format!("arg{}", i + 1));
+    }
+
+    let ret_ty = rust_type(&ret);
+    let send = match *ret.unqualified() {
+        Encoding::Float | Encoding::Double => "msg_send_fpret",
+        ref ty if is_stret(ty) => "msg_send_stret",
+        _ => "msg_send",
+    };
+
+    let mut out = String::new();
+    out.push_str("    /// ~~~objective-c\n");
+    out.push_str(format!("    /// {} {}\n", if is_class { "+" } else { "-" },
+                         dump::selector_declaration(selector, method.types.as_slice())).as_slice());
+    out.push_str("    /// ~~~\n");
+    out.push_str("    #[inline]\n");
+    out.push_str(format!("    pub unsafe fn {}({}){} {{\n",
+                         method_ident(is_class, selector), params.connect(", "),
+                         if ret_ty.as_slice() == "()" { String::new() } else { format!(" -> {}", ret_ty) }).as_slice());
+    out.push_str(format!("        objc::{}()({})\n", send, call_args.connect(", ")).as_slice());
+    out.push_str("    }\n");
+    out
+}
+
+/// Returns the getter and setter methods of a property, with their type
+/// encodings reconstructed from the property's attributes.
+fn property_accessors(property: &PropertyInfo) -> Vec<MethodInfo> {
+    let name = property.name.as_slice();
+    let mut ty = "@".to_string();
+    let mut getter = name.to_string();
+    let mut setter = Some(naming::setter_selector(name));
+    for attribute in property.attributes.as_slice().split(',') {
+        match attribute.slice_shift_char() {
+            Some(('T', types)) => ty = types.to_string(),
+            Some(('G', name)) => getter = name.to_string(),
+            Some(('S', name)) => setter = setter.map(|_| name.to_string()),
+            Some(('R', _)) => setter = None,
+            _ => {}
+        }
+    }
+    let mut accessors = vec![MethodInfo { name: getter, types: format!("{}@:", ty) }];
+    match setter {
+        Some(setter) => accessors.push(MethodInfo { name: setter, types: format!("v@:{}", ty) }),
+        None => {}
+    }
+    accessors
+}
+
+fn ivar_accessor(ivar: &IvarInfo) -> String {
+    let ty = encoding::parse(ivar.types.as_slice()).map_or("libc::c_void".to_string(), |ty| rust_type(&ty));
+    let ident = naming::ivar_ident(ivar.name.as_slice());
+    let mut out = String::new();
+    out.push_str("    /// ~~~objective-c\n");
+    out.push_str(format!("    /// {};\n", encoding::parse(ivar.types.as_slice())
+                              .map_or(format!("void *{}", ivar.name), |ty| ty.c_declaration(ivar.name.as_slice())))
+                    .as_slice());
+    out.push_str("    /// ~~~\n");
+    out.push_str("    #[inline]\n");
+    out.push_str(format!("    pub unsafe fn {}(this: objc::Id) -> *mut {} {{\n", ident, ty).as_slice());
+    out.push_str(format!("        let ivar = objc::InstanceVariable {{ raw: this.isa().get_instance_variable(\"{}\") }};\n",
+                         ivar.name).as_slice());
+    out.push_str(format!("        (this.raw as *mut u8).offset(ivar.get_offset()) as *mut {}\n", ty).as_slice());
+    out.push_str("    }\n");
+    out
+}
+
+/// Returns the Rust type used for a value of the given encoding.
+pub fn rust_type(ty: &Encoding) -> String {
+    match *ty {
+        Encoding::Char => "libc::c_char".to_string(),
+        Encoding::Short => "libc::c_short".to_string(),
+        Encoding::Int => "libc::c_int".to_string(),
+        Encoding::Long => "libc::c_long".to_string(),
+        Encoding::LongLong => "libc::c_longlong".to_string(),
+        Encoding::UChar => "libc::c_uchar".to_string(),
+        Encoding::UShort => "libc::c_ushort".to_string(),
+        Encoding::UInt => "libc::c_uint".to_string(),
+        Encoding::ULong => "libc::c_ulong".to_string(),
+        Encoding::ULongLong => "libc::c_ulonglong".to_string(),
+        Encoding::Float => "f32".to_string(),
+        Encoding::Double => "f64".to_string(),
+        Encoding::Bool => "bool".to_string(),
+        Encoding::Void => "()".to_string(),
+        Encoding::String => "*const libc::c_char".to_string(),
+        Encoding::Object(_) | Encoding::Block => "objc::Id".to_string(),
+        Encoding::Class => "objc::Class".to_string(),
+        Encoding::Sel => "objc::Selector".to_string(),
+        Encoding::Unknown | Encoding::BitField(_) => "libc::c_void".to_string(),
+        Encoding::Pointer(box Encoding::Unknown) => "*mut libc::c_void".to_string(),
+        Encoding::Pointer(box Encoding::Const(ref ty)) => format!("*const {}", rust_type(&**ty)),
+        Encoding::Pointer(ref ty) => format!("*mut {}", rust_type(&**ty)),
+        Encoding::Array(len, ref ty) => format!("[{}, ..{}]", rust_type(&**ty), len),
+        Encoding::Struct(ref tag, _) | Encoding::Union(ref tag, _) if tag.as_slice() == "?" => {
+            "libc::c_void".to_string()
+        }
+        Encoding::Struct(ref tag, _) | Encoding::Union(ref tag, _) => {
+            tag.as_slice().trim_left_chars('_').to_string()
+        }
+        Encoding::Const(ref ty) => rust_type(&**ty),
+    }
+}
+
+/// Returns `true` if a struct of the given encoding is returned in memory,
+/// requiring `objc_msgSend_stret`. Structs without field information are
+/// assumed to be.
+///
+/// This follows the same rules as `objc::returns_in_memory`, for the
+/// architecture the generator is built for.
+fn is_stret(ty: &Encoding) -> bool {
+    match *ty {
+        Encoding::Struct(_, ref fields) | Encoding::Union(_, ref fields) => {
+            fields.is_empty() || match size_of(ty) {
+                None => true,
+                // Structs of one, two, four or eight bytes are returned in
+                // registers.
+                Some(size) if cfg!(target_arch = "x86") => {
+                    match size { 1 | 2 | 4 | 8 => false, _ => true }
+                }
+                Some(size) => size > 16,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// The size of a value of the given encoding on the architecture the
+/// generator is built for, ignoring padding.
+fn size_of(ty: &Encoding) -> Option<uint> {
+    match *ty {
+        Encoding::Char | Encoding::UChar | Encoding::Bool => Some(1),
+        Encoding::Short | Encoding::UShort => Some(2),
+        Encoding::Int | Encoding::UInt | Encoding::Float => Some(4),
+        Encoding::LongLong | Encoding::ULongLong | Encoding::Double => Some(8),
+        Encoding::Array(len, ref ty) => size_of(&**ty).map(|size| len * size),
+        Encoding::Struct(_, ref fields) => {
+            fields.iter().fold(Some(0), |total, field| total.and_then(|t| size_of(field).map(|s| t + s)))
+        }
+        Encoding::Union(_, ref fields) => {
+            fields.iter().fold(Some(0), |max, field| max.and_then(|m| size_of(field).map(|s| ::std::cmp::max(m, s))))
+        }
+        Encoding::Const(ref ty) => size_of(&**ty),
+        Encoding::BitField(_) | Encoding::Unknown | Encoding::Void => None,
+        // Longs, pointers, objects, classes and selectors are a word wide.
+        _ => Some(::std::mem::size_of::<uint>()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Options, generate, is_stret, split_namespace};
+    use encoding::Encoding;
+    use metadata::ClassInfo;
+
+    #[test]
+    pub fn test_split_namespace() {
+        assert_eq!(split_namespace("NSString"), ("NS", "String"));
+        assert_eq!(split_namespace("CALayer"), ("CA", "Layer"));
+        assert_eq!(split_namespace("Foo"), ("", "Foo"));
+        assert_eq!(split_namespace("URL"), ("", "URL"));
+        assert_eq!(split_namespace("NSURLSession"), ("NS", "URLSession"));
+        assert_eq!(split_namespace("NSURL"), ("NS", "URL"));
+        assert_eq!(split_namespace("CGColor"), ("CG", "Color"));
+        assert_eq!(split_namespace("RSWidget"), ("RS", "Widget"));
+    }
+
+    #[test]
+    pub fn test_is_stret() {
+        let ints = |n: uint| Encoding::Struct("ints".to_string(), Vec::from_elem(n, Encoding::Int));
+        assert!(!is_stret(&ints(2)));
+        assert!(is_stret(&ints(5)));
+        assert!(is_stret(&Encoding::Struct("opaque".to_string(), Vec::new())));
+        assert!(!is_stret(&Encoding::Int));
+        // Three ints fit in registers on x86_64, but not on i386.
+        assert_eq!(is_stret(&ints(3)), cfg!(target_arch = "x86"));
+    }
+
+    #[test]
+    pub fn test_generate() {
+        let info = ClassInfo::from_json(r#"{
+            "name": "RSFoo",
+            "superclass": "NSObject",
+            "protocols": [],
+            "ivars": [{ "name": "_count", "types": "i" }],
+            "properties": [{ "name": "name", "attributes": "T@\"NSString\",C,N,V_name" }],
+            "class_methods": [{ "name": "newFoo:withBar:", "types": "@32@0:8@16i24" }],
+            "instance_methods": [{ "name": "origin", "types": "{CGPoint=dd}16@0:8" }]
+        }"#).unwrap();
+        let rust = generate(&info, &Options::new());
+        let rust = rust.as_slice();
+        assert!(rust.contains("pub type RSFoo = objc::Id;"));
+        assert!(rust.contains("impl objc::GetClass for RSClassFoo {"));
+        assert!(rust.contains("    /// + (id)newFoo:(id)arg1 withBar:(int)arg2\n"));
+        assert!(rust.contains("pub unsafe fn c_newFoo_withBar_(class: objc::Class, arg1: objc::Id, arg2: libc::c_int) -> objc::Id {"));
+        assert!(rust.contains("pub unsafe fn i_origin(this: objc::Id) -> CGPoint {\n        objc::msg_send()("));
+        assert!(rust.contains("pub unsafe fn i_name(this: objc::Id) -> objc::Id {"));
+        assert!(rust.contains("pub unsafe fn i_setName_(this: objc::Id, arg1: objc::Id) {"));
+        assert!(rust.contains("pub unsafe fn iv__count(this: objc::Id) -> *mut libc::c_int {"));
+    }
+}
@@ -16,6 +16,7 @@
 #define OBJC_RS_HEADERS_H
 
 #include <objc/message.h>
+#include <objc/objc-exception.h>
 #include <objc/objc.h>
 #include <objc/runtime.h>
 
@@ -0,0 +1,137 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Class hierarchy introspection.
+
+use std::collections::HashMap;
+
+use Class;
+
+/// A class together with all of the classes that inherit from it.
+pub struct ClassTree {
+    pub class: Class,
+    /// The direct subclasses, sorted by name.
+    pub subclasses: Vec<ClassTree>,
+}
+
+impl ClassTree {
+    /// Computes the tree of every registered class that inherits from
+    /// `root`.
+    pub unsafe fn new(root: Class) -> ClassTree {
+        let mut children: HashMap<Class, Vec<Class>> = HashMap::new();
+        for class in Class::all() {
+            let sup = class.get_super_class();
+            if sup != Class::nil() {
+                match children.get_mut(&sup) {
+                    Some(subclasses) => { subclasses.push(class); continue; }
+                    None => {}
+                }
+                children.insert(sup, vec![class]);
+            }
+        }
+        ClassTree::build(root, &children)
+    }
+
+    unsafe fn build(class: Class, children: &HashMap<Class, Vec<Class>>) -> ClassTree {
+        let mut subclasses = match children.get(&class) {
+            Some(subclasses) => subclasses.clone(),
+            None => Vec::new(),
+        };
+        subclasses.sort_by(|a, b| a.get_name().cmp(&b.get_name()));
+        ClassTree {
+            class: class,
+            subclasses: subclasses.into_iter().map(|c| ClassTree::build(c, children)).collect(),
+        }
+    }
+
+    /// The number of classes in the tree, including the root.
+    pub fn len(&self) -> uint {
+        self.subclasses.iter().fold(1, |n, tree| n + tree.len())
+    }
+
+    /// Returns `true` if `class` appears anywhere in the tree.
+    pub fn contains(&self, class: Class) -> bool {
+        self.class == class || self.subclasses.iter().any(|tree| tree.contains(class))
+    }
+
+    /// Renders the tree as a Graphviz DOT document, with an edge from each
+    /// class to its superclass.
+    ///
+    /// If `metaclasses` is `true`, each class is also connected to its
+    /// metaclass with a dashed edge, and the metaclasses are connected to
+    /// their own superclasses, matching the familiar `isa` diagram.
+    pub unsafe fn to_dot(&self, metaclasses: bool) -> String {
+        let mut dot = String::from_str("digraph classes {\n    rankdir = BT;\n    node [shape = box];\n");
+        self.write_dot(&mut dot, metaclasses);
+        dot.push_str("}\n");
+        dot
+    }
+
+    unsafe fn write_dot(&self, dot: &mut String, metaclasses: bool) {
+        let name = self.class.get_name();
+        dot.push_str(format!("    \"{}\";\n", name).as_slice());
+        let sup = self.class.get_super_class();
+        if sup != Class::nil() {
+            dot.push_str(format!("    \"{}\" -> \"{}\";\n", name, sup.get_name()).as_slice());
+        }
+        if metaclasses {
+            let meta = self.class.get_meta_class();
+            dot.push_str(format!("    \"meta {}\" [style = dashed];\n", name).as_slice());
+            dot.push_str(format!("    \"{}\" -> \"meta {}\" [style = dashed, label = \"isa\"];\n",
+                                 name, name).as_slice());
+            if sup != Class::nil() {
+                dot.push_str(format!("    \"meta {}\" -> \"meta {}\";\n", name, sup.get_name()).as_slice());
+            } else {
+                // The root metaclass inherits from the root class.
+                let meta_sup = meta.get_super_class();
+                dot.push_str(format!("    \"meta {}\" -> \"{}\";\n", name, meta_sup.get_name()).as_slice());
+            }
+        }
+        for tree in self.subclasses.iter() {
+            tree.write_dot(dot, metaclasses);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClassTree;
+    use class;
+
+    // Required for testing `NSString`.
+    #[link(name = "Foundation", kind = "framework")]
+    extern {}
+
+    #[test]
+    pub fn test_class_tree() {
+        unsafe {
+            let tree = ClassTree::new(class("NSObject"));
+            assert!(tree.contains(class("NSString")));
+            assert!(tree.len() > 1);
+
+            let leaf = ClassTree::new(class("NSString"));
+            assert!(!leaf.contains(class("NSObject")));
+        }
+    }
+
+    #[test]
+    pub fn test_class_tree_to_dot() {
+        unsafe {
+            let dot = ClassTree::new(class("NSObject")).to_dot(true);
+            assert!(dot.as_slice().starts_with("digraph classes {"));
+            assert!(dot.as_slice().contains("\"NSString\" -> \"NSObject\";"));
+            assert!(dot.as_slice().contains("\"meta NSObject\" -> \"NSObject\";"));
+        }
+    }
+}
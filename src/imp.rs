@@ -0,0 +1,323 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Calling method implementations, and writing them in Rust.
+
+use block::{ConcreteBlock, RcBlock};
+use exception::guard_panic;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::atomic::{AtomicUint, INIT_ATOMIC_UINT, SeqCst};
+use {Class, Id, Impl, Selector, returns_in_memory};
+
+/// Types that can be passed as the arguments of a method implementation,
+/// following the receiver and selector: tuples of up to twelve elements.
+pub trait MessageArguments {
+    /// The number of arguments.
+    fn len(&self) -> uint;
+
+    /// Calls `imp` with `receiver`, `selector` and these arguments.
+    unsafe fn call_imp<R>(self, imp: Impl, receiver: Id, selector: Selector) -> R;
+}
+
+/// Types that can be used as method implementations: `Impl` itself, and
+/// `extern fn(Id, Selector, ...) -> R` function pointers with up to twelve
+/// arguments following the selector.
+pub trait IntoImp {
+    fn into_imp(self) -> Impl;
+}
+
+/// Types that an `Impl` can be converted to. The conversion is unchecked, so
+/// the signature must match the method's.
+pub trait FromImp {
+    unsafe fn from_imp(imp: Impl) -> Self;
+}
+
+impl IntoImp for Impl {
+    #[inline]
+    fn into_imp(self) -> Impl {
+        self
+    }
+}
+
+impl FromImp for Impl {
+    #[inline]
+    unsafe fn from_imp(imp: Impl) -> Impl {
+        imp
+    }
+}
+
+macro_rules! imp_impl(
+    ($($t:ident),*) => (
+        impl<$($t,)* R> IntoImp for extern "C" fn(Id, Selector $(, $t)*) -> R {
+            #[inline]
+            fn into_imp(self) -> Impl {
+                Impl { raw: unsafe { mem::transmute(self) } }
+            }
+        }
+
+        impl<$($t,)* R> IntoImp for unsafe extern "C" fn(Id, Selector $(, $t)*) -> R {
+            #[inline]
+            fn into_imp(self) -> Impl {
+                Impl { raw: unsafe { mem::transmute(self) } }
+            }
+        }
+
+        impl<$($t,)* R> FromImp for extern "C" fn(Id, Selector $(, $t)*) -> R {
+            #[inline]
+            unsafe fn from_imp(imp: Impl) -> extern "C" fn(Id, Selector $(, $t)*) -> R {
+                mem::transmute(imp.raw)
+            }
+        }
+
+        impl<$($t,)* R> FromImp for unsafe extern "C" fn(Id, Selector $(, $t)*) -> R {
+            #[inline]
+            unsafe fn from_imp(imp: Impl) -> unsafe extern "C" fn(Id, Selector $(, $t)*) -> R {
+                mem::transmute(imp.raw)
+            }
+        }
+    );
+)
+
+imp_impl!()
+imp_impl!(A)
+imp_impl!(A, B)
+imp_impl!(A, B, C)
+imp_impl!(A, B, C, D)
+imp_impl!(A, B, C, D, E)
+imp_impl!(A, B, C, D, E, F)
+imp_impl!(A, B, C, D, E, F, G)
+imp_impl!(A, B, C, D, E, F, G, H)
+imp_impl!(A, B, C, D, E, F, G, H, I)
+imp_impl!(A, B, C, D, E, F, G, H, I, J)
+imp_impl!(A, B, C, D, E, F, G, H, I, J, K)
+imp_impl!(A, B, C, D, E, F, G, H, I, J, K, L)
+
+macro_rules! message_args_impl(
+    ($len:expr $(, $a:ident : $t:ident)*) => (
+        impl<$($t),*> MessageArguments for ($($t,)*) {
+            #[inline]
+            fn len(&self) -> uint {
+                $len
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn call_imp<R>(self, imp: Impl, receiver: Id, selector: Selector) -> R {
+                let imp: unsafe extern fn(Id, Selector $(, $t)*) -> R = imp.cast();
+                let ($($a,)*) = self;
+                imp(receiver, selector $(, $a)*)
+            }
+        }
+    );
+)
+
+message_args_impl!(0)
+message_args_impl!(1, a: A)
+message_args_impl!(2, a: A, b: B)
+message_args_impl!(3, a: A, b: B, c: C)
+message_args_impl!(4, a: A, b: B, c: C, d: D)
+message_args_impl!(5, a: A, b: B, c: C, d: D, e: E)
+message_args_impl!(6, a: A, b: B, c: C, d: D, e: E, f: F)
+message_args_impl!(7, a: A, b: B, c: C, d: D, e: E, f: F, g: G)
+message_args_impl!(8, a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H)
+message_args_impl!(9, a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I)
+message_args_impl!(10, a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J)
+message_args_impl!(11, a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K)
+message_args_impl!(12, a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L)
+
+/// Closures that can implement a method: those taking the receiver followed
+/// by up to eleven arguments.
+///
+/// The closure is wrapped in a block that runs it under `guard_panic`, so a
+/// panic never unwinds into the Objective-C runtime.
+///
+/// The method may be called from any thread that sends the message. The
+/// closure is not required to be `Send`, so that it can capture objects,
+/// but callers of the unsafe functions taking it must make sure that it is
+/// only called where its captured state may be used.
+pub trait IntoMethodBlock<A, R> {
+    fn into_method_block(self) -> RcBlock<A, R>;
+}
+
+macro_rules! method_block_impl(
+    ($($a:ident : $t:ident),*) => (
+        impl<$($t: 'static,)* R: 'static, X: 'static> IntoMethodBlock<(Id, $($t,)*), R> for X
+                where X: Fn(Id $(, $t)*) -> R {
+            fn into_method_block(self) -> RcBlock<(Id, $($t,)*), R> {
+                ConcreteBlock::new(move |&: this: Id $(, $a: $t)*| {
+                    unsafe { guard_panic(|| self(this $(, $a)*)) }
+                }).copy()
+            }
+        }
+    );
+)
+
+method_block_impl!()
+method_block_impl!(a: A)
+method_block_impl!(a: A, b: B)
+method_block_impl!(a: A, b: B, c: C)
+method_block_impl!(a: A, b: B, c: C, d: D)
+method_block_impl!(a: A, b: B, c: C, d: D, e: E)
+method_block_impl!(a: A, b: B, c: C, d: D, e: E, f: F)
+method_block_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G)
+method_block_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H)
+method_block_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I)
+method_block_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J)
+method_block_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K)
+
+static GENERATION: AtomicUint = INIT_ATOMIC_UINT;
+
+/// Invalidates every `ImpCache`.
+///
+/// The crate's functions that add or change method implementations call
+/// this, so it is only needed after changing them through `ffi` directly.
+pub fn invalidate_caches() {
+    GENERATION.fetch_add(1, SeqCst);
+}
+
+/// A cache of method implementations, for sending the same messages many
+/// times without the cost of `objc_msgSend`.
+///
+/// Each implementation is looked up once per class and selector, and the
+/// cache is cleared whenever `invalidate_caches` is called.
+///
+/// ~~~rust
+/// let mut cache = ImpCache::new();
+/// for &view in views.iter() {
+///     let _: () = cache.send(view, selector("setNeedsDisplay:"), (1i8,));
+/// }
+/// ~~~
+pub struct ImpCache {
+    /// Keyed by class, selector, and whether the method returns in memory.
+    imps: HashMap<(Class, Selector, bool), Impl>,
+    generation: uint,
+}
+
+impl ImpCache {
+    pub fn new() -> ImpCache {
+        ImpCache {
+            imps: HashMap::new(),
+            generation: GENERATION.load(SeqCst),
+        }
+    }
+
+    /// Returns the implementation that `class` uses for `selector`, looking
+    /// it up if it is not cached.
+    pub unsafe fn get(&mut self, class: Class, selector: Selector) -> Impl {
+        self.lookup(class, selector, false)
+    }
+
+    /// Like `get`, for a method that returns a struct in memory. See
+    /// `Class::get_method_implementation_stret`.
+    pub unsafe fn get_stret(&mut self, class: Class, selector: Selector) -> Impl {
+        self.lookup(class, selector, true)
+    }
+
+    unsafe fn lookup(&mut self, class: Class, selector: Selector, stret: bool) -> Impl {
+        let generation = GENERATION.load(SeqCst);
+        if generation != self.generation {
+            self.imps.clear();
+            self.generation = generation;
+        }
+        match self.imps.get(&(class, selector, stret)) {
+            Some(&imp) => return imp,
+            None => {}
+        }
+        let imp = if stret {
+            class.get_method_implementation_stret(selector)
+        } else {
+            class.get_method_implementation(selector)
+        };
+        self.imps.insert((class, selector, stret), imp);
+        imp
+    }
+
+    /// Calls the implementation that `receiver` uses for `selector`
+    /// directly, with a tuple of the arguments following the selector.
+    ///
+    /// Unlike a message send, the receiver must not be nil.
+    #[inline]
+    pub unsafe fn send<A: MessageArguments, R>(&mut self, receiver: Id, selector: Selector, args: A) -> R {
+        let class = receiver.get_class();
+        let imp = if returns_in_memory::<R>() {
+            self.get_stret(class, selector)
+        } else {
+            self.get(class, selector)
+        };
+        args.call_imp(imp, receiver, selector)
+    }
+
+    /// The number of cached implementations.
+    pub fn len(&self) -> uint {
+        self.imps.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::c_int;
+
+    use super::ImpCache;
+    use swizzle::Swizzle;
+    use {Id, Selector, class, msg_send, selector};
+    use NSClassObject;
+
+    extern fn multiply(_this: Id, _cmd: Selector, a: c_int, b: c_int) -> c_int {
+        a * b
+    }
+
+    #[test]
+    pub fn test_typed_imp() {
+        unsafe {
+            let test_class = class("NSObject").allocate_unique_class_pair("RSTypedImpTest", 0);
+            let imp = multiply as extern fn(Id, Selector, c_int, c_int) -> c_int;
+            assert!(test_class.add_raw_method(selector("multiply:by:"), imp, "i@:ii"));
+            test_class.register_class_pair();
+
+            let object = NSClassObject::c_new(test_class);
+            let result: c_int = msg_send()(object, selector("multiply:by:"), 6 as c_int, 7 as c_int);
+            assert_eq!(result, 42);
+
+            let imp: extern fn(Id, Selector, c_int, c_int) -> c_int = {
+                test_class.get_method_implementation(selector("multiply:by:")).cast()
+            };
+            assert_eq!(imp(object, selector("multiply:by:"), 2, 3), 6);
+        }
+    }
+
+    #[test]
+    pub fn test_imp_cache() {
+        unsafe {
+            let test_class = class("NSObject").allocate_unique_class_pair("RSImpCacheTest", 0);
+            assert!(test_class.add_method_fn(selector("add:"), "i@:i", |&: _this: Id, a: c_int| a + 1));
+            test_class.register_class_pair();
+            let object = NSClassObject::c_new(test_class);
+
+            let mut cache = ImpCache::new();
+            let result: c_int = cache.send(object, selector("add:"), (1 as c_int,));
+            assert_eq!(result, 2);
+            let result: c_int = cache.send(object, selector("add:"), (2 as c_int,));
+            assert_eq!(result, 3);
+            assert_eq!(cache.len(), 1);
+
+            {
+                let _swizzle = Swizzle::with_fn(test_class, selector("add:"), |&: _this: Id, a: c_int| a + 2).unwrap();
+                let result: c_int = cache.send(object, selector("add:"), (1 as c_int,));
+                assert_eq!(result, 3);
+            }
+            let result: c_int = cache.send(object, selector("add:"), (1 as c_int,));
+            assert_eq!(result, 2);
+        }
+    }
+}
@@ -14,14 +14,80 @@
 
 //! An Objective-C runtime wrapper for Rust.
 
-#![feature(phase)]
+#![feature(macro_rules, phase, slicing_syntax, unsafe_destructor)]
 
 extern crate libc;
-
+extern crate serialize;
+#[cfg(feature = "trace")]
+extern crate time;
+
+/// Sends a message, as `msg_send()(receiver, selector(name), args...)`
+/// does. With the `trace` feature enabled, the message is recorded to the
+/// active `trace::Sink`.
+///
+/// ~~~rust
+/// let length: uint = msg_send!(string, "length");
+/// let character: u16 = msg_send!(string, "characterAtIndex:", 0u);
+/// ~~~
+#[macro_export]
+macro_rules! msg_send(
+    ($receiver:expr, $name:expr $(, $arg:expr)*) => ({
+        let receiver: $crate::Id = $receiver;
+        let sel = $crate::selector($name);
+        $crate::trace_send(receiver, sel, || $crate::msg_send()(receiver, sel $(, $arg)*))
+    });
+)
+
+/// Sends a message to `super` from a method declared by `class`, as
+/// `[super name:args...]` does.
+///
+/// The message is looked up from the superclass of `class`, which must be
+/// the class declaring the calling method:
+///
+/// ~~~rust
+/// let rs_view = class("RSView");
+/// rs_view.add_method_fn(selector("dealloc"), "v@:", move |&: this: Id| {
+///     ...
+///     let _: () = msg_send_super!(rs_view, this, "dealloc");
+/// });
+/// ~~~
+#[macro_export]
+macro_rules! msg_send_super(
+    ($class:expr, $receiver:expr, $name:expr $(, $arg:expr)*) => ({
+        let class: $crate::Class = $class;
+        let mut sup = $crate::Super::new($receiver, class.get_super_class());
+        $crate::msg_send_super()(&mut sup, $crate::selector($name) $(, $arg)*)
+    });
+)
+
+pub mod block;
+pub mod class_pair;
+pub mod diff;
+pub mod dump;
+pub mod encoding;
+pub mod exception;
+pub mod forward;
+pub mod generate;
+pub mod hierarchy;
+pub mod imp;
+pub mod metadata;
+pub mod mock;
+pub mod naming;
+pub mod resolve;
+pub mod swizzle;
+#[cfg(feature = "trace")]
+pub mod trace;
+
+use block::{Block, RcBlock};
+use encoding::{Encoding, MethodEncoding};
+use imp::{FromImp, IntoImp, IntoMethodBlock, MessageArguments};
 use std::c_str::CString;
-use std::c_vec::CVec;
 use std::fmt;
 use std::mem;
+use std::ptr;
+use std::raw;
+use std::sync::{MUTEX_INIT, StaticMutex};
+use std::sync::atomic::{AtomicUint, INIT_ATOMIC_UINT, SeqCst};
 
 /// Foreign functions and types for the Objective-C bridging API.
 #[cfg(target_os="macos")]
@@ -53,12 +119,114 @@ pub mod ffi {
     compile_error!("The `objc` crate only supports OS X")
 }
 
+/// An owned list of runtime handles, as returned by the `copy_*_list`
+/// functions.
+///
+/// The runtime allocates these lists with `malloc`, so the buffer is freed
+/// when the list is dropped. The element types are `#[repr(C)]` single-field
+/// wrappers around the corresponding `ffi` handles, so they share their
+/// layout.
+pub struct List<T> {
+    ptr: *mut T,
+    len: uint,
+}
+
+impl<T> List<T> {
+    /// Takes ownership of a buffer returned by the runtime.
+    pub unsafe fn from_raw(ptr: *mut T, len: uint) -> List<T> {
+        List { ptr: ptr, len: len }
+    }
+
+    #[inline]
+    pub fn len(&self) -> uint {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn as_slice<'a>(&'a self) -> &'a [T] {
+        if self.ptr.is_null() {
+            &[]
+        } else {
+            unsafe { mem::transmute(raw::Slice { data: self.ptr as *const T, len: self.len }) }
+        }
+    }
+
+    #[inline]
+    pub fn get<'a>(&'a self, index: uint) -> Option<&'a T> {
+        self.as_slice().get(index)
+    }
+
+    #[inline]
+    pub fn iter<'a>(&'a self) -> std::slice::Items<'a, T> {
+        self.as_slice().iter()
+    }
+
+    /// Consumes the list, yielding its elements by value.
+    #[inline]
+    pub fn into_iter(self) -> MoveItems<T> {
+        MoveItems { list: self, index: 0 }
+    }
+}
+
+impl<T> Index<uint, T> for List<T> {
+    #[inline]
+    fn index<'a>(&'a self, index: &uint) -> &'a T {
+        &self.as_slice()[*index]
+    }
+}
+
+#[unsafe_destructor]
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        unsafe { libc::free(self.ptr as *mut libc::c_void); }
+    }
+}
+
+impl<T: fmt::Show> fmt::Show for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.as_slice().fmt(f)
+    }
+}
+
+/// An iterator that moves out of a `List`.
+pub struct MoveItems<T> {
+    list: List<T>,
+    index: uint,
+}
+
+impl<T> Iterator<T> for MoveItems<T> {
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        if self.index < self.list.len {
+            let item = unsafe { ptr::read(self.list.ptr.offset(self.index as int) as *const T) };
+            self.index += 1;
+            Some(item)
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        let remaining = self.list.len - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
 /// An Objective-C class definition.
 #[deriving(PartialEq, Eq, Hash)]
+#[repr(C)]
 pub struct Class {
     pub raw: ffi::Class,
 }
 
+static UNIQUE_CLASS_COUNT: AtomicUint = INIT_ATOMIC_UINT;
+
 /// A convenience wrapper for `Class::get`.
 pub unsafe fn class(name: &str) -> Class {
     Class::get(name)
@@ -89,6 +257,24 @@ impl Class {
         ffi::class_isMetaClass(self.raw) == ffi::YES
     }
 
+    /// Returns an iterator over the superclass chain, starting with the
+    /// immediate superclass and ending with the root class.
+    pub unsafe fn super_classes(self) -> SuperClasses {
+        SuperClasses { next: self.get_super_class() }
+    }
+
+    /// Returns `true` if `self` is `other` or inherits from it.
+    pub unsafe fn is_subclass_of(self, other: Class) -> bool {
+        self != Class::nil() && (self == other || self.super_classes().any(|c| c == other))
+    }
+
+    /// The metaclass of this class. For a metaclass, this is the root
+    /// metaclass.
+    #[inline]
+    pub unsafe fn get_meta_class(self) -> Class {
+        self.as_id().get_class()
+    }
+
     pub unsafe fn get_instance_size(self) -> uint {
         ffi::class_getInstanceSize(self.raw) as uint
     }
@@ -113,12 +299,10 @@ impl Class {
         self.add_ivar_raw(name, mem::size_of::<T>(), mem::align_of::<T>(), types)
     }
 
-    pub unsafe fn copy_ivar_list(self) -> CVec<ffi::Ivar> {
+    pub unsafe fn copy_ivar_list(self) -> List<InstanceVariable> {
         let mut count = 0;
         let ptr = ffi::class_copyIvarList(self.raw, &mut count);
-        CVec::new_with_dtor(ptr, count as uint, proc() {
-            libc::free(ptr as *mut libc::c_void);
-        })
+        List::from_raw(ptr as *mut InstanceVariable, count as uint)
     }
 
     pub unsafe fn get_ivar_layout(self) -> String {
@@ -141,17 +325,42 @@ impl Class {
         ffi::class_getProperty(self.raw, name.to_c_str().as_ptr())
     }
 
-    pub unsafe fn copy_property_list(self) -> CVec<ffi::objc_property_t> {
+    pub unsafe fn copy_property_list(self) -> List<Property> {
         let mut count = 0;
         let ptr = ffi::class_copyPropertyList(self.raw, &mut count);
-        CVec::new_with_dtor(ptr, count as uint, proc() {
-            libc::free(ptr as *mut libc::c_void);
-        })
-    }
-
-    pub unsafe fn add_method(self, name: Selector, imp: Impl, types: &str) -> bool {
-        ffi::class_addMethod(self.raw, name.raw, mem::transmute(imp),
-                             types.to_c_str().as_ptr()) == ffi::YES
+        List::from_raw(ptr as *mut Property, count as uint)
+    }
+
+    /// Adds a method implemented by `imp`, which is an `Impl` or a function
+    /// pointer such as `extern fn(Id, Selector, c_int) -> Id`.
+    ///
+    /// The implementation is installed as it is, with nothing to stop a
+    /// panic unwinding into the runtime, which is undefined behaviour. Rust
+    /// methods should be added with `add_method_fn`, which guards them; a
+    /// function added here must run its body under `exception::guard_panic`
+    /// if it may panic.
+    pub unsafe fn add_raw_method<F: IntoImp>(self, name: Selector, imp: F, types: &str) -> bool {
+        let added = ffi::class_addMethod(self.raw, name.raw, imp.into_imp().raw,
+                                         types.to_c_str().as_ptr()) == ffi::YES;
+        imp::invalidate_caches();
+        added
+    }
+
+    /// Adds a method implemented by `block`, which is called with the
+    /// receiver followed by the method's arguments, but not the selector.
+    ///
+    /// The runtime retains the heap block, so its captured state lives as
+    /// long as the method. A `ConcreteBlock` is passed by copying it to the
+    /// heap first. See `implementation_with_block`.
+    pub unsafe fn add_method_with_block<A, R>(self, name: Selector, block: &RcBlock<A, R>, types: &str) -> bool {
+        let imp = implementation_with_block(&**block);
+        if self.add_raw_method(name, imp, types) {
+            record_block_method(self, imp);
+            true
+        } else {
+            remove_block(imp);
+            false
+        }
     }
 
     pub unsafe fn get_instance_method(self, name: Selector) -> ffi::Method {
@@ -162,24 +371,47 @@ impl Class {
         ffi::class_getClassMethod(self.raw, name.raw)
     }
 
-    pub unsafe fn copy_method_list(self) -> CVec<ffi::Method> {
+    pub unsafe fn copy_method_list(self) -> List<Method> {
         let mut count = 0;
         let ptr = ffi::class_copyMethodList(self.raw, &mut count);
-        CVec::new_with_dtor(ptr, count as uint, proc() {
-            libc::free(ptr as *mut libc::c_void);
-        })
+        List::from_raw(ptr as *mut Method, count as uint)
     }
 
-    pub unsafe fn replace_method(self, name: Selector, imp: Impl, types: &str) -> Impl {
-        mem::transmute(ffi::class_replaceMethod(self.raw, name.raw, mem::transmute(imp),
-                                                types.to_c_str().as_ptr()))
+    pub unsafe fn replace_method<F: IntoImp>(self, name: Selector, imp: F, types: &str) -> Impl {
+        let old = ffi::class_replaceMethod(self.raw, name.raw, imp.into_imp().raw, types.to_c_str().as_ptr());
+        imp::invalidate_caches();
+        Impl { raw: old }
+    }
+
+    /// Adds a method implemented by a Rust closure, which is called with the
+    /// receiver followed by the method's arguments.
+    ///
+    /// Unlike `add_raw_method`, a panic in the closure does not unwind into the
+    /// runtime, but is handled by `exception::guard_panic`.
+    pub unsafe fn add_method_fn<A, R, F: IntoMethodBlock<A, R>>(self, name: Selector, types: &str,
+                                                             closure: F) -> bool {
+        self.add_method_with_block(name, &closure.into_method_block(), types)
+    }
+
+    /// Replaces a method's implementation with `block`, returning the
+    /// previous implementation. See `add_method_with_block`.
+    pub unsafe fn replace_method_with_block<A, R>(self, name: Selector, block: &RcBlock<A, R>,
+                                                  types: &str) -> Impl {
+        let imp = implementation_with_block(&**block);
+        record_block_method(self, imp);
+        self.replace_method(name, imp, types)
     }
 
     pub unsafe fn get_method_implementation(self, name: Selector) -> Impl {
-        mem::transmute(ffi::class_getMethodImplementation(self.raw, name.raw))
+        Impl { raw: ffi::class_getMethodImplementation(self.raw, name.raw) }
     }
 
-    // TODO: class_getMethodImplementation_stret
+    /// Like `get_method_implementation`, for methods returning a struct in
+    /// memory: if there is no such method, the forwarding implementation
+    /// returned is the one that expects a return pointer.
+    pub unsafe fn get_method_implementation_stret(self, name: Selector) -> Impl {
+        Impl { raw: ffi::class_getMethodImplementation_stret(self.raw, name.raw) }
+    }
 
     pub unsafe fn responds_to_selector(self, name: ffi::SEL) -> bool {
         ffi::class_respondsToSelector(self.raw, name) == ffi::YES
@@ -207,12 +439,10 @@ impl Class {
         ffi::class_conformsToProtocol(self.raw, protocol) == ffi::YES
     }
 
-    pub unsafe fn copy_protocol_list(self) -> CVec<*mut ffi::Protocol> {
+    pub unsafe fn copy_protocol_list(self) -> List<Protocol> {
         let mut count = 0;
         let ptr = ffi::class_copyProtocolList(self.raw, &mut count);
-        CVec::new_with_dtor(ptr, count as uint, proc() {
-            libc::free(ptr as *mut libc::c_void);
-        })
+        List::from_raw(ptr as *mut Protocol, count as uint)
     }
 
     pub unsafe fn get_version(self) -> int {
@@ -235,8 +465,27 @@ impl Class {
         }
     }
 
+    /// Creates a class pair named `prefix` followed by a number, which is
+    /// chosen so that no other class has the name. Use `get_name` to find the
+    /// name.
+    ///
+    /// Returns nil if the class cannot be allocated for another reason, such
+    /// as `self` being a class still under construction.
+    pub unsafe fn allocate_unique_class_pair(self, prefix: &str, extra_bytes: uint) -> Class {
+        loop {
+            let name = format!("{}{}", prefix, UNIQUE_CLASS_COUNT.fetch_add(1, SeqCst));
+            let class = self.allocate_class_pair(name.as_slice(), extra_bytes);
+            if class != Class::nil() || Class::get(name.as_slice()) == Class::nil() {
+                return class;
+            }
+        }
+    }
+
+    /// Destroys a class and its metaclass. There must be no instances of the
+    /// class or its subclasses; `class_pair::ClassPair` checks this.
     pub unsafe fn dispose_class_pair(self) {
         ffi::objc_disposeClassPair(self.raw);
+        imp::invalidate_caches();
     }
 
     pub unsafe fn register_class_pair(self) {
@@ -257,18 +506,34 @@ impl Class {
 
     // Obtaining Class Definitions
 
-    // FIXME: dunno how best to wrap this
+    /// The raw `objc_getClassList` call. Prefer `Class::all`, which takes
+    /// care of sizing the buffer.
     pub unsafe fn get_list(buffer: *mut ffi::Class, buffer_count: libc::c_int) -> libc::c_int {
         ffi::objc_getClassList(buffer, buffer_count)
     }
 
-    // FIXME: should return a vector of `Class`, not `ffi::Class`
-    pub unsafe fn copy_list() -> CVec<ffi::Class> {
+    /// Returns an iterator over every class registered with the runtime.
+    ///
+    /// Classes may be registered by other threads between sizing the buffer
+    /// and filling it, so the buffer is regrown until the whole list fits.
+    pub unsafe fn all() -> Classes {
+        let mut classes: Vec<Class> = Vec::new();
+        loop {
+            let capacity = classes.capacity();
+            let count = Class::get_list(classes.as_mut_ptr() as *mut ffi::Class,
+                                        capacity as libc::c_int) as uint;
+            if count <= capacity {
+                classes.set_len(count);
+                return Classes { classes: classes, index: 0 };
+            }
+            classes = Vec::with_capacity(count);
+        }
+    }
+
+    pub unsafe fn copy_list() -> List<Class> {
         let mut count = 0;
         let ptr = ffi::objc_copyClassList(&mut count);
-        CVec::new_with_dtor(ptr, count as uint, proc() {
-            libc::free(ptr as *mut libc::c_void);
-        })
+        List::from_raw(ptr as *mut Class, count as uint)
     }
 
     pub unsafe fn look_up(name: &str) -> Class {
@@ -294,6 +559,75 @@ impl Class {
     }
 }
 
+/// An iterator over the superclass chain of a class, created by
+/// `Class::super_classes`.
+pub struct SuperClasses {
+    next: Class,
+}
+
+impl Iterator<Class> for SuperClasses {
+    #[inline]
+    fn next(&mut self) -> Option<Class> {
+        if self.next == Class::nil() {
+            None
+        } else {
+            let class = self.next;
+            self.next = unsafe { class.get_super_class() };
+            Some(class)
+        }
+    }
+}
+
+/// An iterator over the classes registered with the runtime, created by
+/// `Class::all`.
+pub struct Classes {
+    classes: Vec<Class>,
+    index: uint,
+}
+
+impl Classes {
+    /// Keeps only the strict subclasses of `sup`.
+    pub unsafe fn subclasses_of(mut self, sup: Class) -> Classes {
+        self.classes.retain(|&class| class != sup && class.is_subclass_of(sup));
+        self
+    }
+
+    /// Keeps only the classes defined in the image at the path `image`.
+    /// Classes created at runtime have no image and are dropped.
+    pub unsafe fn in_image(mut self, image: &str) -> Classes {
+        self.classes.retain(|&class| {
+            let name = ffi::class_getImageName(class.raw);
+            !name.is_null() && String::from_raw_buf(name as *const libc::c_uchar).as_slice() == image
+        });
+        self
+    }
+
+    /// Keeps only the classes that conform to `protocol`, either directly or
+    /// through one of their superclasses.
+    pub unsafe fn conforming_to(mut self, protocol: Protocol) -> Classes {
+        self.classes.retain(|&class| {
+            class.conforms_to_protocol(protocol.raw) ||
+                class.super_classes().any(|c| c.conforms_to_protocol(protocol.raw))
+        });
+        self
+    }
+}
+
+impl Iterator<Class> for Classes {
+    #[inline]
+    fn next(&mut self) -> Option<Class> {
+        let class = self.classes.as_slice().get(self.index).map(|&class| class);
+        self.index += 1;
+        class
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        let remaining = self.classes.len().saturating_sub(self.index);
+        (remaining, Some(remaining))
+    }
+}
+
 impl fmt::Show for Class {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         unsafe {
@@ -316,7 +650,7 @@ impl fmt::Show for Id {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         unsafe {
             if *self != Id::nil() {
-                write!(f, "instance of {}", self.isa().get_name())
+                write!(f, "instance of {}", self.get_class().get_name())
             } else {
                 write!(f, "nil")
             }
@@ -337,6 +671,19 @@ impl Id {
         Class { raw: (*self.raw).isa }
     }
 
+    /// Returns `true` if the object is an instance of `class` or one of its
+    /// subclasses.
+    #[inline]
+    pub unsafe fn is_kind_of(self, class: Class) -> bool {
+        self != Id::nil() && self.get_class().is_subclass_of(class)
+    }
+
+    /// Returns `true` if the object is an instance of exactly `class`.
+    #[inline]
+    pub unsafe fn is_member_of(self, class: Class) -> bool {
+        self != Id::nil() && self.get_class() == class
+    }
+
     // Instantiating Classes
 
     pub unsafe fn destruct_instance(self) -> *mut libc::c_void {
@@ -345,6 +692,13 @@ impl Id {
 
     // Working with Instances
 
+    /// The class of the object, or nil for nil, as `object_getClass`
+    /// returns it. Unlike `isa`, this works for tagged pointers and
+    /// non-pointer isa fields.
+    pub unsafe fn get_class(self) -> Class {
+        Class { raw: ffi::object_getClass(self.raw) }
+    }
+
     pub unsafe fn copy(self, size: uint) -> Id {
         Id { raw: ffi::object_copy(self.raw, size as libc::size_t) }
     }
@@ -361,6 +715,7 @@ impl Id {
 }
 
 /// An instance variable.
+#[repr(C)]
 pub struct InstanceVariable {
     pub raw: ffi::Ivar,
 }
@@ -381,27 +736,75 @@ impl InstanceVariable {
     }
 }
 
-/// The superclass of an instance.
+/// A receiver and the superclass at which to start looking up methods, for
+/// sending messages to `super` with `msg_send_super`.
+#[repr(C)]
 pub struct Super {
-    pub raw: *mut ffi::Struct_objc_super,
+    pub raw: ffi::Struct_objc_super,
 }
 
 impl Super {
+    /// Creates a `Super` for sending messages to `receiver` that use the
+    /// implementations of `superclass`.
+    ///
+    /// `superclass` must be the superclass of the class declaring the
+    /// calling method, not of `receiver.isa()`, which may be a subclass of
+    /// it. Otherwise a subclass that inherits the method will call it again
+    /// forever.
+    #[inline]
+    pub fn new(receiver: Id, superclass: Class) -> Super {
+        Super {
+            raw: ffi::Struct_objc_super {
+                receiver: receiver.raw,
+                class: superclass.raw,
+            },
+        }
+    }
+
     /// The instance of a class.
     #[inline]
-    pub unsafe fn receiver(self) -> Id {
-        Id { raw: (*self.raw).receiver }
+    pub fn receiver(self) -> Id {
+        Id { raw: self.raw.receiver }
     }
 
     /// The superclass to message.
     #[inline]
-    pub unsafe fn class(self) -> Class {
-        Class { raw: (*self.raw).class }
+    pub fn class(self) -> Class {
+        Class { raw: self.raw.class }
     }
 }
 
 // Sending Messages
 
+/// Calls `send`, which sends `selector` to `receiver`. This is used by
+/// `msg_send!`, and records nothing without the `trace` feature.
+#[cfg(not(feature = "trace"))]
+#[inline(always)]
+pub unsafe fn trace_send<T, F: FnOnce() -> T>(_receiver: Id, _selector: Selector, send: F) -> T {
+    send()
+}
+
+#[cfg(feature = "trace")]
+pub use trace::trace_send;
+
+/// Returns `true` if a value of type `R` is returned in memory, through a
+/// pointer passed by the caller, rather than in registers. Such types need
+/// the `_stret` variants of the runtime functions.
+///
+/// Only the size of `R` is considered, which is enough for the structs that
+/// Objective-C methods return.
+pub fn returns_in_memory<R>() -> bool {
+    let size = mem::size_of::<R>();
+    if cfg!(target_arch = "x86") {
+        // Structs of one, two, four or eight bytes are returned in registers.
+        match size { 0 | 1 | 2 | 4 | 8 => false, _ => true }
+    } else {
+        size > 16
+    }
+}
+
+/// Returns `objc_msgSend` with the return type `T`. Messages sent through
+/// it are not seen by the `trace` feature; prefer `msg_send!`.
 pub fn msg_send<T>() -> unsafe extern fn(Id, Selector, ...) -> T {
     unsafe { mem::transmute(ffi::objc_msgSend) }
 }
@@ -414,15 +817,16 @@ pub fn msg_send_stret<T>() -> unsafe extern fn(Id, Selector, ...) -> T {
     unsafe { mem::transmute(ffi::objc_msgSend_stret) }
 }
 
-pub fn msg_send_super<T>() -> unsafe extern fn(Id, Selector, ...) -> T {
+pub fn msg_send_super<T>() -> unsafe extern fn(*mut Super, Selector, ...) -> T {
     unsafe { mem::transmute(ffi::objc_msgSendSuper) }
 }
 
-pub fn msg_send_super_stret<T>() -> unsafe extern fn(Id, Selector, ...) -> T {
+pub fn msg_send_super_stret<T>() -> unsafe extern fn(*mut Super, Selector, ...) -> T {
     unsafe { mem::transmute(ffi::objc_msgSendSuper_stret) }
 }
 
 
+#[repr(C)]
 pub struct Method {
     pub raw: ffi::Method,
 }
@@ -430,24 +834,45 @@ pub struct Method {
 impl Method {
     // Working with Methods
 
-    // #[inline]
-    // pub unsafe fn invoke<T: 'static>(self, reciever: Id) -> T {
-    //     unsafe {
-    //         match type_id::<T>() {
-    //             id if id == type_id::<Id>() => mem::transmute(ffi::method_invoke(reciever.raw as *mut ffi::Struct_objc_object, self.raw)),
-    //             _                           => mem::transmute(ffi::method_invoke_stret(reciever.raw as *mut ffi::Struct_objc_object, self.raw)),
-    //         }
-    //     }
-    // }
+    /// Calls the method's implementation on `receiver`, bypassing message
+    /// dispatch, with a tuple of the arguments following the selector.
+    ///
+    /// This is `method_invoke`, but the implementation is called with its
+    /// real signature, so the compiler picks the right convention for the
+    /// return type `R` and no `_stret` variant is needed.
+    ///
+    /// Panics if the method is null, or if the number of arguments does not
+    /// match the method's.
+    pub unsafe fn invoke<A: MessageArguments, R>(self, receiver: Id, args: A) -> R {
+        if self.raw.is_null() {
+            panic!("cannot invoke a null method");
+        }
+        // Every method takes the receiver and selector, but a method with a
+        // malformed type encoding may report fewer arguments.
+        match self.get_number_of_arguments().checked_sub(2) {
+            Some(expected) if expected == args.len() => (),
+            Some(expected) => {
+                panic!("method takes {} arguments, but {} were given", expected, args.len())
+            }
+            None => panic!("method {} has a malformed type encoding", self.get_name()),
+        }
+        args.call_imp(self.get_implementation(), receiver, self.selector())
+    }
+
+    #[inline]
+    pub unsafe fn selector(self) -> Selector {
+        Selector { raw: ffi::method_getName(self.raw) }
+    }
 
+    /// The name of the method's selector.
     #[inline]
     pub unsafe fn get_name(self) -> String {
-        String::from_raw_buf(ffi::method_getName(self.raw) as *const libc::c_uchar)
+        self.selector().get_name()
     }
 
     #[inline]
     pub unsafe fn get_implementation(self) -> Impl {
-        mem::transmute(ffi::method_getImplementation(self.raw))
+        Impl { raw: ffi::method_getImplementation(self.raw) }
     }
 
     #[inline]
@@ -466,24 +891,110 @@ impl Method {
                                                   index as libc::c_uint) as *const libc::c_char, true)
     }
 
-    // method_getReturnType
+    // skipped: method_getReturnType   (the same as `Method::copy_return_type`)
+    // skipped: method_getArgumentType (the same as `Method::copy_argument_type`)
 
     #[inline]
     pub unsafe fn get_number_of_arguments(self) -> uint {
         ffi::method_getNumberOfArguments(self.raw) as uint
     }
 
-    // method_getArgumentType
-    // method_getDescription
+    /// The parsed method type encoding, or `None` if it cannot be parsed.
+    pub unsafe fn encoding(self) -> Option<MethodEncoding> {
+        encoding::parse_method(self.get_type_encoding().as_str().unwrap_or(""))
+    }
+
+    /// The parsed return type, or `None` if it cannot be parsed.
+    pub unsafe fn return_encoding(self) -> Option<Encoding> {
+        encoding::parse(self.copy_return_type().as_str().unwrap_or(""))
+    }
+
+    /// The parsed type of the argument at `index`, counting the receiver
+    /// and selector, or `None` if there is no such argument or it cannot be
+    /// parsed.
+    pub unsafe fn argument_encoding(self, index: uint) -> Option<Encoding> {
+        if index >= self.get_number_of_arguments() {
+            return None;
+        }
+        encoding::parse(self.copy_argument_type(index).as_str().unwrap_or(""))
+    }
 
     #[inline]
-    pub unsafe fn set_implementation(self, imp: Impl) -> Impl {
-        mem::transmute(ffi::method_setImplementation(self.raw, mem::transmute(imp)))
+    pub unsafe fn get_description(self) -> MethodDescription {
+        MethodDescription { raw: *ffi::method_getDescription(self.raw) }
+    }
+
+    #[inline]
+    pub unsafe fn set_implementation<F: IntoImp>(self, imp: F) -> Impl {
+        let old = ffi::method_setImplementation(self.raw, imp.into_imp().raw);
+        imp::invalidate_caches();
+        Impl { raw: old }
     }
 
     #[inline]
     pub unsafe fn exchange_implementations(self, other: Method) {
         ffi::method_exchangeImplementations(self.raw, other.raw);
+        imp::invalidate_caches();
+    }
+}
+
+impl fmt::Show for Method {
+    /// Prints the method's declaration, such as `(char)isEqual:(id)arg1`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        unsafe {
+            if !self.raw.is_null() {
+                write!(f, "{}", dump::method_declaration(*self))
+            } else {
+                write!(f, "null")
+            }
+        }
+    }
+}
+
+/// The selector and type encoding of a method, as declared by a protocol.
+#[repr(C)]
+pub struct MethodDescription {
+    pub raw: ffi::Struct_objc_method_description,
+}
+
+impl MethodDescription {
+    #[inline]
+    pub unsafe fn selector(self) -> Selector {
+        Selector { raw: self.raw.name }
+    }
+
+    /// The name of the selector.
+    #[inline]
+    pub unsafe fn get_name(self) -> String {
+        self.selector().get_name()
+    }
+
+    /// The method type encoding, or an empty string if there is none.
+    pub unsafe fn get_type_encoding(self) -> String {
+        if self.raw.types.is_null() {
+            String::new()
+        } else {
+            String::from_raw_buf(self.raw.types as *const libc::c_uchar)
+        }
+    }
+
+    /// The parsed method type encoding, or `None` if it cannot be parsed.
+    pub unsafe fn encoding(self) -> Option<MethodEncoding> {
+        encoding::parse_method(self.get_type_encoding().as_slice())
+    }
+}
+
+impl fmt::Show for MethodDescription {
+    /// Prints the method's declaration, such as `(char)isEqual:(id)arg1`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        unsafe {
+            if !self.raw.name.is_null() {
+                write!(f, "{}", dump::selector_declaration(self.get_name().as_slice(),
+                                                           self.get_type_encoding().as_slice()))
+            } else {
+                write!(f, "null")
+            }
+        }
     }
 }
 
@@ -510,11 +1021,15 @@ pub unsafe fn copy_class_names_for_image(image: &str) -> Vec<String> {
 }
 
 /// A method selector.
-#[deriving(PartialEq, Eq, Hash)]
+#[deriving(Clone, PartialEq, Eq, Hash)]
 pub struct Selector {
     pub raw: ffi::SEL,
 }
 
+// Selectors are registered once, and never freed.
+unsafe impl Send for Selector {}
+unsafe impl Sync for Selector {}
+
 /// A convenience wrapper for `Selector::register_name`.
 pub unsafe fn selector(name: &str) -> Selector {
     Selector::register_name(name)
@@ -547,28 +1062,91 @@ impl fmt::Show for Selector {
     }
 }
 
-// Working with Protocols
+/// A formal protocol.
+#[deriving(PartialEq, Eq, Hash)]
+#[repr(C)]
+pub struct Protocol {
+    pub raw: *mut ffi::Protocol,
+}
+
+impl Protocol {
+    // Working with Protocols
+
+    pub unsafe fn get(name: &str) -> Protocol {
+        Protocol { raw: ffi::objc_getProtocol(name.to_c_str().as_ptr()) }
+    }
+
+    pub unsafe fn copy_list() -> List<Protocol> {
+        let mut count = 0;
+        let ptr = ffi::objc_copyProtocolList(&mut count);
+        List::from_raw(ptr as *mut Protocol, count as uint)
+    }
+
+    pub unsafe fn get_name(self) -> String {
+        String::from_raw_buf(ffi::protocol_getName(self.raw) as *const libc::c_uchar)
+    }
+
+    pub unsafe fn copy_method_description_list(self, is_required: bool, is_instance: bool)
+                                               -> List<MethodDescription> {
+        let mut count = 0;
+        let ptr = ffi::protocol_copyMethodDescriptionList(self.raw,
+                                                          if is_required { ffi::YES } else { ffi::NO },
+                                                          if is_instance { ffi::YES } else { ffi::NO },
+                                                          &mut count);
+        List::from_raw(ptr as *mut MethodDescription, count as uint)
+    }
+
+    pub unsafe fn copy_protocol_list(self) -> List<Protocol> {
+        let mut count = 0;
+        let ptr = ffi::protocol_copyProtocolList(self.raw, &mut count);
+        List::from_raw(ptr as *mut Protocol, count as uint)
+    }
+
+    pub unsafe fn conforms_to_protocol(self, other: Protocol) -> bool {
+        ffi::protocol_conformsToProtocol(self.raw, other.raw) == ffi::YES
+    }
+}
+
+impl fmt::Show for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        unsafe {
+            if !self.raw.is_null() {
+                write!(f, "{}", self.get_name())
+            } else {
+                write!(f, "null")
+            }
+        }
+    }
+}
 
-// objc_getProtocol
-// objc_copyProtocolList
 // objc_allocateProtocol
 // objc_registerProtocol
 // protocol_addMethodDescription
 // protocol_addProtocol
 // protocol_addProperty
-// protocol_getName
 // protocol_isEqual
-// protocol_copyMethodDescriptionList
 // protocol_getMethodDescription
 // protocol_copyPropertyList
 // protocol_getProperty
-// protocol_copyProtocolList
-// protocol_conformsToProtocol
 
-// Working with Properties
+/// A declared property.
+#[repr(C)]
+pub struct Property {
+    pub raw: ffi::objc_property_t,
+}
+
+impl Property {
+    // Working with Properties
+
+    pub unsafe fn get_name(self) -> String {
+        String::from_raw_buf(ffi::property_getName(self.raw) as *const libc::c_uchar)
+    }
+
+    pub unsafe fn get_attributes(self) -> String {
+        String::from_raw_buf(ffi::property_getAttributes(self.raw) as *const libc::c_uchar)
+    }
+}
 
-// property_getName
-// property_getAttributes
 // property_copyAttributeValue
 // property_copyAttributeList
 
@@ -576,9 +1154,60 @@ impl fmt::Show for Selector {
 
 // objc_enumerationMutation
 // objc_setEnumerationMutationHandler
-// imp_implementationWithBlock
-// imp_getBlock
-// imp_removeBlock
+
+/// Creates a method implementation that calls `block`. The block takes the
+/// receiver as its first argument, followed by the method's arguments; the
+/// selector is not passed.
+///
+/// The block is copied, and the copy is released by `remove_block`.
+pub unsafe fn implementation_with_block<A, R>(block: &Block<A, R>) -> Impl {
+    Impl { raw: ffi::imp_implementationWithBlock(block.as_id().raw) }
+}
+
+/// Returns the block of an implementation created by
+/// `implementation_with_block`, or `None` for any other implementation.
+pub unsafe fn get_block<A, R>(imp: Impl) -> Option<*mut Block<A, R>> {
+    let block = ffi::imp_getBlock(imp.raw);
+    if block.is_null() { None } else { Some(block as *mut Block<A, R>) }
+}
+
+/// Releases the block of an implementation created by
+/// `implementation_with_block`. The implementation must not be called
+/// afterwards.
+pub unsafe fn remove_block(imp: Impl) -> bool {
+    ffi::imp_removeBlock(imp.raw) == ffi::YES
+}
+
+static BLOCK_METHODS_LOCK: StaticMutex = MUTEX_INIT;
+static mut BLOCK_METHODS: *mut Vec<(Class, Impl)> = 0 as *mut Vec<(Class, Impl)>;
+
+/// Records that `class` owns `imp`, which was created for one of its methods
+/// by `add_method_with_block` or `replace_method_with_block`.
+fn record_block_method(class: Class, imp: Impl) {
+    unsafe {
+        let _guard = BLOCK_METHODS_LOCK.lock();
+        if BLOCK_METHODS.is_null() {
+            BLOCK_METHODS = mem::transmute(box Vec::<(Class, Impl)>::new());
+        }
+        (*BLOCK_METHODS).push((class, imp));
+    }
+}
+
+/// Forgets and returns the block implementations owned by `class`, for the
+/// caller to release with `remove_block`.
+fn take_block_methods(class: Class) -> Vec<Impl> {
+    unsafe {
+        let _guard = BLOCK_METHODS_LOCK.lock();
+        if BLOCK_METHODS.is_null() {
+            return Vec::new();
+        }
+        let methods = mem::replace(&mut *BLOCK_METHODS, Vec::new());
+        let (owned, others): (Vec<_>, Vec<_>) = methods.into_iter().partition(|&(owner, _)| owner == class);
+        *BLOCK_METHODS = others;
+        owned.into_iter().map(|(_, imp)| imp).collect()
+    }
+}
+
 // objc_loadWeak
 // objc_storeWeak
 
@@ -588,10 +1217,30 @@ impl fmt::Show for Selector {
 // Category
 // objc_property_t
 
-/// A method implementation.
-pub type Impl = extern "C" fn(Id, Selector, ...) -> Id;
+/// An untyped method implementation.
+///
+/// Function pointers with the signature of a method, such as
+/// `extern fn(Id, Selector, c_int) -> Id`, convert to and from an `Impl`
+/// with the `IntoImp` and `FromImp` traits.
+pub struct Impl {
+    pub raw: ffi::IMP,
+}
+
+impl Impl {
+    /// Converts a typed function pointer to an `Impl`.
+    #[inline]
+    pub fn from_fn<F: IntoImp>(imp: F) -> Impl {
+        imp.into_imp()
+    }
+
+    /// Converts the implementation to a typed function pointer, which must
+    /// match the method's real signature.
+    #[inline]
+    pub unsafe fn cast<F: FromImp>(self) -> F {
+        FromImp::from_imp(self)
+    }
+}
 
-// objc_method_description
 // objc_method_list
 // objc_cache
 // objc_protocol_list
@@ -601,7 +1250,7 @@ pub type Impl = extern "C" fn(Id, Selector, ...) -> Id;
 
 // objc_AssociationPolicy
 
-trait GetClass {
+pub trait GetClass {
     fn get_class_name(self) -> &'static str;
 
     /// Returns the runtime class handle.
@@ -632,7 +1281,7 @@ impl NSClassObject {
     /// ~~~
     #[inline]
     pub unsafe fn c_initialize(class: Class) -> Class {
-        msg_send()(class.as_id(), selector("initialize"))
+        msg_send!(class.as_id(), "initialize")
     }
 
     /// ~~~objective-c
@@ -640,7 +1289,7 @@ impl NSClassObject {
     /// ~~~
     #[inline]
     pub unsafe fn c_load(class: Class) {
-        msg_send()(class.as_id(), selector("load"))
+        msg_send!(class.as_id(), "load")
     }
 
     ////////////////////////////////////////////////////////////////////////////
@@ -652,7 +1301,7 @@ impl NSClassObject {
     /// ~~~
     #[inline]
     pub unsafe fn c_alloc(class: Class) -> Id {
-        msg_send()(class.as_id(), selector("alloc"))
+        msg_send!(class.as_id(), "alloc")
     }
 
     // TODO: + allocWithZone:
@@ -662,7 +1311,7 @@ impl NSClassObject {
     /// ~~~
     #[inline]
     pub unsafe fn i_init(this: Id) -> Id {
-        msg_send()(this, selector("init"))
+        msg_send!(this, "init")
     }
 
     /// ~~~objective-c
@@ -670,7 +1319,7 @@ impl NSClassObject {
     /// ~~~
     #[inline]
     pub unsafe fn i_copy(this: Id) -> Id {
-        msg_send()(this, selector("copy"))
+        msg_send!(this, "copy")
     }
 
     // TODO: + copyWithZone:
@@ -680,7 +1329,7 @@ impl NSClassObject {
     /// ~~~
     #[inline]
     pub unsafe fn i_mutable_copy(this: Id) -> Id {
-        msg_send()(this, selector("mutable_copy"))
+        msg_send!(this, "mutable_copy")
     }
 
     // TODO: + mutableCopyWithZone:
@@ -690,7 +1339,7 @@ impl NSClassObject {
     /// ~~~
     #[inline]
     pub unsafe fn i_dealloc(this: Id) -> Id {
-        msg_send()(this, selector("dealloc"))
+        msg_send!(this, "dealloc")
     }
 
     /// ~~~objective-c
@@ -698,7 +1347,7 @@ impl NSClassObject {
     /// ~~~
     #[inline]
     pub unsafe fn c_new(class: Class) -> Id {
-        msg_send()(class.as_id(), selector("new"))
+        msg_send!(class.as_id(), "new")
     }
 
     ////////////////////////////////////////////////////////////////////////////
@@ -707,17 +1356,17 @@ impl NSClassObject {
 
     #[inline]
     pub unsafe fn c_class(class: Class) -> Class {
-        msg_send()(class.as_id(), selector("class"))
+        msg_send!(class.as_id(), "class")
     }
 
     #[inline]
     pub unsafe fn c_superclass(class: Class) -> Class {
-        msg_send()(class.as_id(), selector("superclass"))
+        msg_send!(class.as_id(), "superclass")
     }
 
     #[inline]
     pub unsafe fn c_isSubclassOfClass_(class: Class, sup: Class) -> bool {
-        ffi::YES == msg_send()(class.as_id(), selector("isSubclassOfClass:"), sup)
+        ffi::YES == msg_send!(class.as_id(), "isSubclassOfClass:", sup)
     }
 
     ////////////////////////////////////////////////////////////////////////////
@@ -739,7 +1388,14 @@ impl NSClassObject {
     // TODO: - methodForSelector:
     // TODO: + instanceMethodForSelector:
     // TODO: + instanceMethodSignatureForSelector:
-    // TODO: - methodSignatureForSelector:
+
+    /// ~~~objective-c
+    /// - (NSMethodSignature *)methodSignatureForSelector:(SEL)aSelector
+    /// ~~~
+    #[inline]
+    pub unsafe fn i_methodSignatureForSelector_(this: Id, aSelector: Selector) -> Id {
+        msg_send!(this, "methodSignatureForSelector:", aSelector)
+    }
 
     ////////////////////////////////////////////////////////////////////////////
     // Describing Objects
@@ -771,15 +1427,49 @@ impl NSClassObject {
     // Forwarding Messages
     ////////////////////////////////////////////////////////////////////////////
 
-    // TODO: - forwardingTargetForSelector:
-    // TODO: - forwardInvocation:
+    /// ~~~objective-c
+    /// - (id)forwardingTargetForSelector:(SEL)aSelector
+    /// ~~~
+    ///
+    /// See `forward::set_forwarding_target`.
+    #[inline]
+    pub unsafe fn i_forwardingTargetForSelector_(this: Id, aSelector: Selector) -> Id {
+        msg_send!(this, "forwardingTargetForSelector:", aSelector)
+    }
+
+    /// ~~~objective-c
+    /// - (void)forwardInvocation:(NSInvocation *)anInvocation
+    /// ~~~
+    ///
+    /// See `forward::set_invocation_handler`.
+    #[inline]
+    pub unsafe fn i_forwardInvocation_(this: Id, anInvocation: Id) {
+        msg_send!(this, "forwardInvocation:", anInvocation)
+    }
 
     ////////////////////////////////////////////////////////////////////////////
     // Dynamically Resolving Methods
     ////////////////////////////////////////////////////////////////////////////
 
-    // TODO: + resolveClassMethod:
-    // TODO: + resolveInstanceMethod:
+    /// ~~~objective-c
+    /// + (BOOL)resolveClassMethod:(SEL)name
+    /// ~~~
+    ///
+    /// See `resolve::set_class_method_resolver`.
+    #[inline]
+    pub unsafe fn c_resolveClassMethod_(class: Class, name: Selector) -> bool {
+        ffi::YES == msg_send!(class.as_id(), "resolveClassMethod:", name)
+    }
+
+    /// ~~~objective-c
+    /// + (BOOL)resolveInstanceMethod:(SEL)name
+    /// ~~~
+    ///
+    /// See `resolve::set_instance_method_resolver`.
+    #[inline]
+    pub unsafe fn c_resolveInstanceMethod_(class: Class, name: Selector) -> bool {
+        ffi::YES == msg_send!(class.as_id(), "resolveInstanceMethod:", name)
+    }
 
     ////////////////////////////////////////////////////////////////////////////
     // Error Handling
@@ -830,8 +1520,11 @@ impl NSClassObject {
 
 #[cfg(test)]
 mod tests {
-    use super::{Class, class};
-    use super::NSObject;
+    use block::ConcreteBlock;
+    use libc::c_int;
+    use encoding::Encoding;
+    use super::{Class, Id, Method, Protocol, Super, class, get_block, msg_send, msg_send_stret, selector};
+    use super::{NSObject, NSClassObject};
 
     // Required for testing `NSString`.
     #[link(name = "Foundation", kind = "framework")]
@@ -881,6 +1574,213 @@ mod tests {
         }
     }
 
+    #[test]
+    pub fn test_class_hierarchy() {
+        unsafe {
+            let supers: Vec<Class> = NSString().super_classes().collect();
+            assert_eq!(supers, vec![class("NSObject")]);
+            assert!(NSString().is_subclass_of(class("NSObject")));
+            assert!(NSString().is_subclass_of(NSString()));
+            assert!(!class("NSObject").is_subclass_of(NSString()));
+            assert_eq!(NSString().get_meta_class(), Class::get_meta("NSString"));
+            assert_eq!(Class::get_meta("NSString").get_meta_class(), Class::get_meta("NSObject"));
+        }
+    }
+
+    #[test]
+    pub fn test_id_is_kind_of() {
+        unsafe {
+            let string = NSClassObject::c_new(NSString());
+            assert!(string.is_kind_of(class("NSObject")));
+            assert!(!string.is_member_of(class("NSObject")));
+            assert!(!Id::nil().is_kind_of(class("NSObject")));
+        }
+    }
+
+    #[test]
+    pub fn test_add_method_with_block() {
+        unsafe {
+            let test_class = class("NSObject").allocate_unique_class_pair("RSBlockMethodTest", 0);
+            let offset: c_int = 40;
+            let block = ConcreteBlock::new(move |&: _this: Id, value: c_int| value + offset).copy();
+            assert!(test_class.add_method_with_block(selector("add:"), &block, "i@:i"));
+            test_class.register_class_pair();
+
+            let object = NSClassObject::c_new(test_class);
+            let result: c_int = msg_send()(object, selector("add:"), 2 as c_int);
+            assert_eq!(result, 42);
+
+            let imp = test_class.get_method_implementation(selector("add:"));
+            assert!(get_block::<(Id, c_int), c_int>(imp).is_some());
+            assert!(get_block::<(Id, c_int), c_int>(class("NSObject").get_method_implementation(selector("init"))).is_none());
+        }
+    }
+
+    #[test]
+    pub fn test_add_method_returning_struct() {
+        #[repr(C)]
+        #[deriving(PartialEq, Show)]
+        struct Rect { x: f64, y: f64, width: f64, height: f64 }
+
+        unsafe {
+            let test_class = class("NSObject").allocate_unique_class_pair("RSStretMethodTest", 0);
+            let types = "{CGRect={CGPoint=dd}{CGSize=dd}}@:d";
+            assert!(test_class.add_method_fn(selector("rectWithWidth:"), types, |&: _this: Id, width: f64| {
+                Rect { x: 1.0, y: 2.0, width: width, height: 4.0 }
+            }));
+            test_class.register_class_pair();
+
+            let object = NSClassObject::c_new(test_class);
+            let rect: Rect = msg_send_stret()(object, selector("rectWithWidth:"), 3.0f64);
+            assert_eq!(rect, Rect { x: 1.0, y: 2.0, width: 3.0, height: 4.0 });
+        }
+    }
+
+    #[test]
+    pub fn test_msg_send_macro() {
+        unsafe {
+            let string: Id = msg_send!(NSString().as_id(), "stringWithUTF8String:", "abc".to_c_str().as_ptr());
+            let length: uint = msg_send!(string, "length");
+            assert_eq!(length, 3);
+            let character: u16 = msg_send!(string, "characterAtIndex:", 1u);
+            assert_eq!(character, 'b' as u16);
+        }
+    }
+
+    #[test]
+    pub fn test_method_invoke() {
+        unsafe {
+            let object = NSClassObject::c_new(class("NSObject"));
+            let method = Method { raw: class("NSObject").get_instance_method(selector("isEqual:")) };
+            let equal: i8 = method.invoke(object, (object,));
+            assert_eq!(equal, 1);
+
+            let method = Method { raw: class("NSObject").get_instance_method(selector("hash")) };
+            let hash: uint = method.invoke(object, ());
+            assert_eq!(hash, msg_send::<uint>()(object, selector("hash")));
+        }
+    }
+
+    #[test]
+    #[should_fail]
+    pub fn test_method_invoke_wrong_arguments() {
+        unsafe {
+            let object = NSClassObject::c_new(class("NSObject"));
+            let method = Method { raw: class("NSObject").get_instance_method(selector("isEqual:")) };
+            let _: i8 = method.invoke(object, ());
+        }
+    }
+
+    #[test]
+    #[should_fail]
+    pub fn test_method_invoke_null() {
+        unsafe {
+            let object = NSClassObject::c_new(class("NSObject"));
+            let method = Method { raw: class("NSObject").get_instance_method(selector("noSuchMethod")) };
+            let _: uint = method.invoke(object, ());
+        }
+    }
+
+    #[test]
+    pub fn test_method_metadata() {
+        unsafe {
+            let method = Method { raw: class("NSObject").get_instance_method(selector("isEqual:")) };
+            assert_eq!(method.selector(), selector("isEqual:"));
+            assert_eq!(method.get_name().as_slice(), "isEqual:");
+            assert_eq!(method.return_encoding(), Some(Encoding::Char));
+            assert_eq!(method.argument_encoding(0), Some(Encoding::Object(None)));
+            assert_eq!(method.argument_encoding(1), Some(Encoding::Sel));
+            assert_eq!(method.argument_encoding(2), Some(Encoding::Object(None)));
+            assert_eq!(method.argument_encoding(3), None);
+            assert_eq!(method.encoding().map(|sig| sig.args.len()), Some(3));
+
+            let description = method.get_description();
+            assert_eq!(description.selector(), selector("isEqual:"));
+            assert_eq!(description.get_type_encoding(), method.get_type_encoding().as_str().unwrap().to_string());
+            assert_eq!(method.to_string().as_slice(), "(char)isEqual:(id)arg1");
+        }
+    }
+
+    #[test]
+    pub fn test_msg_send_super() {
+        unsafe {
+            let test_class = class("NSObject").allocate_unique_class_pair("RSSuperTest", 0);
+            assert!(test_class.add_method_fn(selector("hash"), "Q@:", move |&: this: Id| -> uint {
+                let hash: uint = msg_send_super!(test_class, this, "hash");
+                hash + 1
+            }));
+            test_class.register_class_pair();
+            let subclass = test_class.allocate_unique_class_pair("RSSuperTestSubclass", 0);
+            subclass.register_class_pair();
+
+            let nsobject_hash = Method { raw: class("NSObject").get_instance_method(selector("hash")) };
+            for &cls in [test_class, subclass].iter() {
+                let object = NSClassObject::c_new(cls);
+                let hash: uint = msg_send()(object, selector("hash"));
+                let super_hash: uint = nsobject_hash.invoke(object, ());
+                assert_eq!(hash, super_hash + 1);
+            }
+
+            let sup = Super::new(Id::nil(), test_class);
+            assert_eq!(sup.receiver(), Id::nil());
+            assert_eq!(sup.class(), test_class);
+        }
+    }
+
+    #[test]
+    pub fn test_allocate_unique_class_pair() {
+        unsafe {
+            let first = class("NSObject").allocate_unique_class_pair("RSUniqueTest", 0);
+            let second = class("NSObject").allocate_unique_class_pair("RSUniqueTest", 0);
+            assert!(first != Class::nil() && second != Class::nil());
+            assert!(first.get_name() != second.get_name());
+            assert!(first.get_name().starts_with("RSUniqueTest"));
+            first.dispose_class_pair();
+            second.dispose_class_pair();
+
+            // A metaclass cannot be subclassed, whatever the name.
+            let meta = Class::get_meta("NSObject");
+            assert_eq!(meta.allocate_unique_class_pair("RSUniqueTest", 0), Class::nil());
+        }
+    }
+
+    #[test]
+    pub fn test_class_copy_method_list() {
+        unsafe {
+            let methods = class("NSObject").copy_method_list();
+            assert!(!methods.is_empty());
+            assert_eq!(methods.iter().count(), methods.len());
+            assert!(methods.into_iter().any(|m| m.get_name().as_slice() == "description"));
+        }
+    }
+
+    #[test]
+    pub fn test_class_copy_list() {
+        unsafe {
+            let classes = Class::copy_list();
+            assert!(classes.iter().any(|&c| c == class("NSObject")));
+            assert!(classes.iter().any(|&c| c == NSString()));
+        }
+    }
+
+    #[test]
+    pub fn test_class_all() {
+        unsafe {
+            assert!(Class::all().any(|c| c == NSString()));
+
+            let subclasses: Vec<Class> = Class::all().subclasses_of(class("NSObject")).collect();
+            assert!(subclasses.contains(&NSString()));
+            assert!(!subclasses.contains(&class("NSObject")));
+
+            let image = NSString().get_image_name();
+            assert!(Class::all().in_image(image.as_slice()).any(|c| c == NSString()));
+            assert!(!Class::all().in_image(image.as_slice()).any(|c| c == class("NSObject")));
+
+            let protocol = Protocol::get("NSCopying");
+            assert!(Class::all().conforming_to(protocol).any(|c| c == NSString()));
+        }
+    }
+
     #[test]
     pub fn test_class_get_image_name() {
         unsafe {
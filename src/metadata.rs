@@ -0,0 +1,266 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Plain, serializable descriptions of runtime metadata.
+//!
+//! These can be captured from the live runtime or written by hand as JSON,
+//! and are consumed by the binding generator. A `Snapshot` records the
+//! metadata of every loaded image, so that API surfaces can be compared
+//! without a live runtime.
+
+use std::io::File;
+use serialize::json;
+
+use {Class, Method, Protocol, class, copy_class_names_for_image, copy_image_names};
+
+/// The metadata of every image and protocol loaded into a process.
+#[deriving(Clone, PartialEq, Show, Encodable, Decodable)]
+pub struct Snapshot {
+    /// The images, sorted by path.
+    pub images: Vec<ImageInfo>,
+    /// The protocols, sorted by name.
+    pub protocols: Vec<ProtocolInfo>,
+}
+
+/// A description of a loaded library or framework.
+#[deriving(Clone, PartialEq, Show, Encodable, Decodable)]
+pub struct ImageInfo {
+    pub path: String,
+    /// The classes defined in the image, sorted by name.
+    pub classes: Vec<ClassInfo>,
+}
+
+/// A description of a protocol.
+#[deriving(Clone, PartialEq, Show, Encodable, Decodable)]
+pub struct ProtocolInfo {
+    pub name: String,
+    /// The protocols this protocol incorporates.
+    pub protocols: Vec<String>,
+    pub methods: Vec<ProtocolMethodInfo>,
+}
+
+/// A description of a method declared by a protocol.
+#[deriving(Clone, PartialEq, Show, Encodable, Decodable)]
+pub struct ProtocolMethodInfo {
+    pub name: String,
+    pub types: String,
+    pub is_required: bool,
+    pub is_instance: bool,
+}
+
+/// A description of a class.
+#[deriving(Clone, PartialEq, Show, Encodable, Decodable)]
+pub struct ClassInfo {
+    pub name: String,
+    pub superclass: Option<String>,
+    pub protocols: Vec<String>,
+    pub ivars: Vec<IvarInfo>,
+    pub properties: Vec<PropertyInfo>,
+    pub class_methods: Vec<MethodInfo>,
+    pub instance_methods: Vec<MethodInfo>,
+}
+
+/// A description of an instance variable.
+#[deriving(Clone, PartialEq, Show, Encodable, Decodable)]
+pub struct IvarInfo {
+    pub name: String,
+    /// The type encoding, such as `i` or `@"NSString"`.
+    pub types: String,
+    /// The offset within the object, if known.
+    pub offset: Option<int>,
+}
+
+/// A description of a declared property.
+#[deriving(Clone, PartialEq, Show, Encodable, Decodable)]
+pub struct PropertyInfo {
+    pub name: String,
+    /// The attribute string, such as `T@"NSString",C,N,V_name`.
+    pub attributes: String,
+}
+
+/// A description of a method.
+#[deriving(Clone, PartialEq, Show, Encodable, Decodable)]
+pub struct MethodInfo {
+    /// The selector name, such as `setFoo:withBar:`.
+    pub name: String,
+    /// The method type encoding, such as `v32@0:8@16i24`.
+    pub types: String,
+}
+
+impl Snapshot {
+    /// Captures the metadata of the current process.
+    pub unsafe fn capture() -> Snapshot {
+        let mut paths = copy_image_names();
+        paths.sort();
+        let images = paths.into_iter().map(|path| {
+            let mut names = copy_class_names_for_image(path.as_slice());
+            names.sort();
+            ImageInfo {
+                path: path,
+                classes: names.iter().map(|name| ClassInfo::from_class(class(name.as_slice()))).collect(),
+            }
+        }).collect();
+
+        let mut protocols: Vec<ProtocolInfo> = Protocol::copy_list().iter()
+                                                                     .map(|&p| ProtocolInfo::from_protocol(p))
+                                                                     .collect();
+        protocols.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Snapshot { images: images, protocols: protocols }
+    }
+
+    /// Returns the description of the class named `name`, in whichever
+    /// image defines it.
+    pub fn find_class<'a>(&'a self, name: &str) -> Option<&'a ClassInfo> {
+        self.images.iter()
+                   .flat_map(|image| image.classes.iter())
+                   .find(|class| class.name.as_slice() == name)
+    }
+
+    /// Decodes a snapshot from JSON.
+    pub fn from_json(s: &str) -> Result<Snapshot, String> {
+        json::decode(s).map_err(|err| err.to_string())
+    }
+
+    /// Encodes the snapshot as JSON.
+    pub fn to_json(&self) -> String {
+        json::encode(self)
+    }
+
+    /// Reads a snapshot from a JSON file.
+    pub fn load(path: &Path) -> Result<Snapshot, String> {
+        match File::open(path).read_to_string() {
+            Ok(contents) => Snapshot::from_json(contents.as_slice()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    /// Writes the snapshot to a JSON file.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        File::create(path).write_str(self.to_json().as_slice()).map_err(|err| err.to_string())
+    }
+}
+
+impl ProtocolInfo {
+    /// Captures the metadata of a protocol.
+    pub unsafe fn from_protocol(protocol: Protocol) -> ProtocolInfo {
+        let mut methods = Vec::new();
+        for &(is_required, is_instance) in [(true, true), (true, false), (false, true), (false, false)].iter() {
+            for desc in protocol.copy_method_description_list(is_required, is_instance).iter() {
+                methods.push(ProtocolMethodInfo {
+                    name: desc.get_name(),
+                    types: desc.get_type_encoding(),
+                    is_required: is_required,
+                    is_instance: is_instance,
+                });
+            }
+        }
+        ProtocolInfo {
+            name: protocol.get_name(),
+            protocols: protocol.copy_protocol_list().iter().map(|p| p.get_name()).collect(),
+            methods: methods,
+        }
+    }
+}
+
+impl ClassInfo {
+    /// Captures the metadata of a loaded class.
+    pub unsafe fn from_class(class: Class) -> ClassInfo {
+        let sup = class.get_super_class();
+        ClassInfo {
+            name: class.get_name(),
+            superclass: if sup != Class::nil() { Some(sup.get_name()) } else { None },
+            protocols: class.copy_protocol_list().iter().map(|p| p.get_name()).collect(),
+            ivars: class.copy_ivar_list().iter().map(|ivar| IvarInfo {
+                name: ivar.get_name(),
+                types: ivar.get_type_encoding(),
+                offset: Some(ivar.get_offset()),
+            }).collect(),
+            properties: class.copy_property_list().iter().map(|property| PropertyInfo {
+                name: property.get_name(),
+                attributes: property.get_attributes(),
+            }).collect(),
+            class_methods: class.get_meta_class().copy_method_list().iter()
+                                .map(|&m| MethodInfo::from_method(m)).collect(),
+            instance_methods: class.copy_method_list().iter()
+                                   .map(|&m| MethodInfo::from_method(m)).collect(),
+        }
+    }
+
+    /// Decodes a class description from JSON.
+    pub fn from_json(s: &str) -> Result<ClassInfo, String> {
+        json::decode(s).map_err(|err| err.to_string())
+    }
+
+    /// Encodes the class description as JSON.
+    pub fn to_json(&self) -> String {
+        json::encode(self)
+    }
+}
+
+impl MethodInfo {
+    /// Captures the selector name and type encoding of a method.
+    pub unsafe fn from_method(method: Method) -> MethodInfo {
+        MethodInfo {
+            name: method.get_name(),
+            types: method.get_type_encoding().as_str().unwrap_or("").to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClassInfo, MethodInfo, Snapshot};
+    use class;
+
+    #[test]
+    pub fn test_class_info_from_class() {
+        unsafe {
+            let info = ClassInfo::from_class(class("NSObject"));
+            assert_eq!(info.name.as_slice(), "NSObject");
+            assert_eq!(info.superclass, None);
+            assert!(info.instance_methods.iter().any(|m| m.name.as_slice() == "description"));
+            assert!(info.class_methods.iter().any(|m| m.name.as_slice() == "alloc"));
+        }
+    }
+
+    #[test]
+    pub fn test_snapshot() {
+        unsafe {
+            let snapshot = Snapshot::capture();
+            assert!(snapshot.images.iter().any(|image| image.path.as_slice() == "/usr/lib/libobjc.A.dylib"));
+            assert_eq!(snapshot.find_class("NSObject").map(|c| c.name.as_slice()), Some("NSObject"));
+            assert!(snapshot.protocols.iter().any(|p| p.name.as_slice() == "NSObject"));
+            assert_eq!(Snapshot::from_json(snapshot.to_json().as_slice()), Ok(snapshot));
+        }
+    }
+
+    #[test]
+    pub fn test_class_info_json() {
+        let json = r#"{
+            "name": "RSFoo",
+            "superclass": "NSObject",
+            "protocols": [],
+            "ivars": [{ "name": "_count", "types": "i" }],
+            "properties": [],
+            "class_methods": [],
+            "instance_methods": [{ "name": "setFoo:", "types": "v24@0:8@16" }]
+        }"#;
+        let info = ClassInfo::from_json(json).unwrap();
+        assert_eq!(info.ivars[0].offset, None);
+        assert_eq!(info.instance_methods,
+                   vec![MethodInfo { name: "setFoo:".to_string(), types: "v24@0:8@16".to_string() }]);
+        assert_eq!(ClassInfo::from_json(info.to_json().as_slice()), Ok(info));
+    }
+}
@@ -0,0 +1,375 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Recording mock objects for testing code that sends Objective-C messages.
+//!
+//! A `Mock` is an instance of a new class that handles stubbed selectors
+//! through message forwarding, and records the messages it receives:
+//!
+//! ~~~rust
+//! let mock = Mock::builder()
+//!     .returning("count", "Q@:", 3u)
+//!     .stub("objectAtIndex:", "@@:Q", |&: invocation: Invocation| unsafe {
+//!         let index: uint = invocation.argument(2);
+//!         invocation.set_return_value(items[index]);
+//!     })
+//!     .expect("count", 1)
+//!     .build();
+//!
+//! code_under_test(mock.object());
+//! assert_eq!(mock.messages()[1].argument::<uint>(0), 0);
+//! // Dropping the mock panics if `count` was not received exactly once.
+//! ~~~
+//!
+//! Messages the mock does not stub raise `NSInvalidArgumentException`, and
+//! are recorded without their arguments, unless its superclass implements
+//! them; those are answered by the superclass and not recorded. Stubbed methods of the superclass
+//! are overridden to be forwarded too, except those returning structs,
+//! which cannot be stubbed.
+//!
+//! The mock's class is a `ClassPair`, so it is disposed of with the mock,
+//! unless the mock object is still retained elsewhere.
+
+use std::fmt;
+use std::mem;
+use std::ptr;
+use std::rc::Rc;
+use std::rt::unwind;
+use std::sync::{Arc, Mutex};
+
+use class_pair::ClassPair;
+use encoding::{Encoding, parse_method};
+use forward::{Invocation, set_invocation_handler};
+use {Class, Id, Impl, NSClassObject, Selector, class, ffi, msg_send, selector};
+
+struct Stub {
+    name: String,
+    types: String,
+    action: Box<Fn(Invocation) + 'static>,
+}
+
+/// A message received by a mock.
+#[deriving(Clone)]
+pub struct ReceivedMessage {
+    pub selector: Selector,
+    /// The type encodings of the arguments following the selector, or
+    /// empty if the message was not stubbed.
+    pub types: Vec<String>,
+    /// The bytes of the arguments following the selector, or empty if the
+    /// message was not stubbed. Objects are recorded as pointers, and are
+    /// not retained.
+    pub arguments: Vec<Vec<u8>>,
+}
+
+impl ReceivedMessage {
+    /// Returns the argument at `index`, counting from the first argument
+    /// after the selector.
+    ///
+    /// Panics if `T` is not the size of the argument.
+    pub unsafe fn argument<T>(&self, index: uint) -> T {
+        let bytes = &self.arguments[index];
+        if bytes.len() != mem::size_of::<T>() {
+            panic!("type of size {} does not match argument {} of size {}",
+                   mem::size_of::<T>(), index, bytes.len());
+        }
+        ptr::read(bytes.as_ptr() as *const T)
+    }
+}
+
+impl fmt::Show for ReceivedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.selector, self.types.connect(", "))
+    }
+}
+
+/// Configures a `Mock`.
+pub struct MockBuilder {
+    superclass: Option<Class>,
+    stubs: Vec<Stub>,
+    expectations: Vec<(String, uint)>,
+}
+
+impl MockBuilder {
+    pub fn new() -> MockBuilder {
+        MockBuilder {
+            superclass: None,
+            stubs: Vec::new(),
+            expectations: Vec::new(),
+        }
+    }
+
+    /// Sets the superclass of the mock's class, which is `NSObject` by
+    /// default.
+    pub fn superclass(mut self, superclass: Class) -> MockBuilder {
+        self.superclass = Some(superclass);
+        self
+    }
+
+    /// Handles the selector `name`, with the method type encoding `types`,
+    /// by calling `action` with the invocation. The action reads the
+    /// arguments from the invocation and sets its return value.
+    pub fn stub<F>(mut self, name: &str, types: &str, action: F) -> MockBuilder
+            where F: Fn(Invocation) + 'static {
+        self.stubs.push(Stub {
+            name: name.to_string(),
+            types: types.to_string(),
+            action: box action,
+        });
+        self
+    }
+
+    /// Handles the selector `name`, with the method type encoding `types`,
+    /// by returning `value`.
+    pub fn returning<T: Copy + 'static>(self, name: &str, types: &str, value: T) -> MockBuilder {
+        self.stub(name, types, move |&: invocation: Invocation| {
+            unsafe { invocation.set_return_value(value) }
+        })
+    }
+
+    /// Expects the mock to receive the selector `name` exactly `times`
+    /// times before it is dropped. Panics if the selector is not stubbed.
+    pub fn expect(mut self, name: &str, times: uint) -> MockBuilder {
+        if !self.stubs.iter().any(|stub| stub.name.as_slice() == name) {
+            panic!("cannot expect {}, which is not stubbed", name);
+        }
+        self.expectations.push((name.to_string(), times));
+        self
+    }
+
+    /// Creates the mock's class and an instance of it.
+    ///
+    /// Panics if the superclass cannot be subclassed, or if a stubbed method
+    /// of the superclass returns a struct.
+    pub unsafe fn build(self) -> Mock {
+        let superclass = self.superclass.unwrap_or_else(|| class("NSObject"));
+        let pair = match ClassPair::new(superclass, "RSMock") {
+            Ok(pair) => pair,
+            Err(err) => panic!("{}", err),
+        };
+        let mock_class = pair.class();
+
+        let stubs: Rc<Vec<(Selector, Stub)>> = Rc::new(self.stubs.into_iter().map(|stub| {
+            (selector(stub.name.as_slice()), stub)
+        }).collect());
+        let messages = Arc::new(Mutex::new(Vec::new()));
+
+        // The superclass's methods are found before forwarding is tried, so
+        // stubs of them are overridden to forward explicitly.
+        for &(name, ref stub) in stubs.iter() {
+            if superclass.get_instance_method(name).is_null() {
+                continue;
+            }
+            match parse_method(stub.types.as_slice()).as_ref().map(|method| method.ret.unqualified()) {
+                Some(&Encoding::Struct(..)) | Some(&Encoding::Union(..)) => {
+                    panic!("cannot stub {}, which {} implements and which returns a struct", name, superclass);
+                }
+                _ => {}
+            }
+            let forward = Impl { raw: mem::transmute(ffi::_objc_msgForward) };
+            mock_class.add_raw_method(name, forward, stub.types.as_slice());
+        }
+
+        let signature_stubs = stubs.clone();
+        let signature_messages = messages.clone();
+        let handler_messages = messages.clone();
+        assert!(set_invocation_handler(mock_class,
+            move |&: _this: Id, name: Selector| {
+                let types = signature_stubs.iter().find(|&&(sel, _)| sel == name).map(|&(_, ref stub)| {
+                    stub.types.clone()
+                });
+                // An unstubbed message is never forwarded, so it is recorded
+                // here, before it raises.
+                if types.is_none() {
+                    signature_messages.lock().push(ReceivedMessage {
+                        selector: name,
+                        types: Vec::new(),
+                        arguments: Vec::new(),
+                    });
+                }
+                types
+            },
+            move |&: _this: Id, invocation: Invocation| {
+                let name = invocation.selector();
+                let count = invocation.number_of_arguments();
+                handler_messages.lock().push(ReceivedMessage {
+                    selector: name,
+                    types: range(2, count).map(|i| invocation.argument_type(i)).collect(),
+                    arguments: range(2, count).map(|i| invocation.argument_bytes(i)).collect(),
+                });
+                match stubs.iter().find(|&&(sel, _)| sel == name) {
+                    Some(&(_, ref stub)) => {
+                        (*stub.action)(invocation);
+                        true
+                    }
+                    None => false,
+                }
+            }));
+        pair.register();
+
+        Mock {
+            object: NSClassObject::c_new(mock_class),
+            pair: pair,
+            messages: messages,
+            expectations: self.expectations.into_iter().map(|(name, times)| {
+                (selector(name.as_slice()), times)
+            }).collect(),
+        }
+    }
+}
+
+/// A mock object, which checks its expectations when dropped.
+pub struct Mock {
+    object: Id,
+    pair: ClassPair,
+    messages: Arc<Mutex<Vec<ReceivedMessage>>>,
+    expectations: Vec<(Selector, uint)>,
+}
+
+impl Mock {
+    pub fn builder() -> MockBuilder {
+        MockBuilder::new()
+    }
+
+    /// The mock's class.
+    #[inline]
+    pub fn class(&self) -> Class {
+        self.pair.class()
+    }
+
+    /// The mock object, which is retained by the `Mock`.
+    #[inline]
+    pub fn object(&self) -> Id {
+        self.object
+    }
+
+    /// The messages received so far, in order.
+    pub fn messages(&self) -> Vec<ReceivedMessage> {
+        self.messages.lock().clone()
+    }
+
+    /// The number of times the selector `name` has been received.
+    pub unsafe fn received(&self, name: &str) -> uint {
+        let name = selector(name);
+        self.messages.lock().iter().filter(|message| message.selector == name).count()
+    }
+
+    /// Checks that each expected selector has been received the expected
+    /// number of times.
+    pub fn verify(&self) -> Result<(), String> {
+        let failures: Vec<String> = self.expectations.iter().filter_map(|&(name, times)| {
+            let count = self.messages.lock().iter().filter(|message| message.selector == name).count();
+            if count == times {
+                None
+            } else {
+                Some(format!("expected {} to be received {} times, but it was received {} times",
+                             name, times, count))
+            }
+        }).collect();
+        if failures.is_empty() { Ok(()) } else { Err(failures.connect("\n")) }
+    }
+}
+
+impl Drop for Mock {
+    /// Releases the mock object, and panics if an expectation was not met,
+    /// unless already panicking. The mock's class is then disposed of.
+    fn drop(&mut self) {
+        unsafe {
+            msg_send::<()>()(self.object, selector("release"));
+        }
+        match self.verify() {
+            Err(ref err) if !unwind::panicking() => panic!("{}", err),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::c_int;
+
+    use exception::catch_exception;
+    use forward::Invocation;
+    use super::Mock;
+    use {msg_send, selector};
+
+    #[test]
+    pub fn test_mock() {
+        unsafe {
+            let mock = Mock::builder()
+                .returning("count", "Q@:", 3u)
+                .stub("add:to:", "i@:ii", |&: invocation: Invocation| {
+                    let a: c_int = invocation.argument(2);
+                    let b: c_int = invocation.argument(3);
+                    invocation.set_return_value(a + b);
+                })
+                .expect("count", 2)
+                .build();
+
+            let count: uint = msg_send()(mock.object(), selector("count"));
+            assert_eq!(count, 3);
+            let sum: c_int = msg_send()(mock.object(), selector("add:to:"), 2 as c_int, 3 as c_int);
+            assert_eq!(sum, 5);
+            assert_eq!(mock.verify().is_ok(), false);
+            let _: uint = msg_send()(mock.object(), selector("count"));
+            assert!(mock.verify().is_ok());
+
+            let messages = mock.messages();
+            assert_eq!(messages.len(), 3);
+            assert_eq!(messages[1].selector, selector("add:to:"));
+            assert_eq!(messages[1].types, vec!["i".to_string(), "i".to_string()]);
+            assert_eq!(messages[1].argument::<c_int>(1), 3);
+            assert_eq!(mock.received("count"), 2);
+        }
+    }
+
+    #[test]
+    pub fn test_unstubbed_message() {
+        unsafe {
+            let mock = Mock::builder().build();
+            let exception = catch_exception(|| {
+                msg_send::<()>()(mock.object(), selector("count"))
+            }).err().unwrap();
+            assert_eq!(exception.name(), Some("NSInvalidArgumentException".to_string()));
+            assert_eq!(mock.received("count"), 1);
+            assert!(mock.messages()[0].arguments.is_empty());
+        }
+    }
+
+    #[test]
+    pub fn test_stub_inherited_method() {
+        unsafe {
+            let mock = Mock::builder().returning("hash", "Q@:", 7u).build();
+            let hash: uint = msg_send()(mock.object(), selector("hash"));
+            assert_eq!(hash, 7);
+            assert_eq!(mock.received("hash"), 1);
+        }
+    }
+
+    #[test]
+    #[should_fail]
+    pub fn test_unmet_expectation() {
+        unsafe {
+            let _mock = Mock::builder()
+                .returning("count", "Q@:", 3u)
+                .expect("count", 1)
+                .build();
+        }
+    }
+
+    #[test]
+    #[should_fail]
+    pub fn test_expect_unstubbed() {
+        Mock::builder().expect("count", 1);
+    }
+}
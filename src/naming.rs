@@ -0,0 +1,208 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversion between selector names and the Rust identifiers prescribed by
+//! the README:
+//!
+//! | Objective-C selector    | Rust method identifier      |
+//! | ----------------------- | --------------------------- |
+//! | `+ newFoo:`             | `c_newFoo_`                 |
+//! | `+ newFoo:withBar:`     | `c_newFoo_withBar_`         |
+//! | `- setFoo:`             | `i_setFoo_`                 |
+//! | `- setFoo:withBar:`     | `i_setFoo_withBar_`         |
+//!
+//! Each `:` becomes a `_`. Leading underscores, as used by private methods
+//! like `_setFoo:`, are kept as they are, since a selector never starts with
+//! a colon. Underscores elsewhere in a selector cannot be told apart from
+//! colons, so such selectors convert to an identifier but not back again;
+//! `is_representable` checks for this.
+//!
+//! The `c_` and `i_` prefixes mean method identifiers can never collide
+//! with Rust keywords. Bare names, such as argument names, can be passed
+//! through `escape_keyword`.
+
+/// Whether a method is a class or an instance method.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum MethodKind {
+    /// A class method, declared with `+`.
+    Class,
+    /// An instance method, declared with `-`.
+    Instance,
+}
+
+impl MethodKind {
+    /// The identifier prefix for methods of this kind.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            MethodKind::Class => "c_",
+            MethodKind::Instance => "i_",
+        }
+    }
+}
+
+/// The strict and reserved keywords of Rust.
+static KEYWORDS: &'static [&'static str] = &[
+    "abstract", "alignof", "as", "be", "box", "break", "const", "continue", "crate", "do", "else",
+    "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop", "match",
+    "mod", "move", "mut", "offsetof", "override", "priv", "proc", "pub", "pure", "ref", "return",
+    "self", "sizeof", "static", "struct", "super", "trait", "true", "type", "typeof", "unsafe",
+    "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Returns `true` if `ident` is a Rust keyword.
+pub fn is_keyword(ident: &str) -> bool {
+    KEYWORDS.contains(&ident)
+}
+
+/// Appends an `_` to `ident` if it is a Rust keyword, so `type` becomes
+/// `type_`.
+pub fn escape_keyword(ident: &str) -> String {
+    if is_keyword(ident) { format!("{}_", ident) } else { ident.to_string() }
+}
+
+/// Returns `true` if the identifier of `selector` converts back to it, that
+/// is, if the selector is made of ASCII letters, digits and colons, with
+/// underscores only at the start.
+pub fn is_representable(selector: &str) -> bool {
+    let rest = selector.trim_left_chars('_');
+    match rest.chars().next() {
+        Some(c) if c.is_ascii() && c.is_alphanumeric() => {}
+        _ => return false,
+    }
+    rest.chars().all(|c| c == ':' || (c.is_ascii() && c.is_alphanumeric()))
+}
+
+/// Returns the Rust identifier for a method, such as `c_newFoo_withBar_`
+/// for the class method `newFoo:withBar:`.
+pub fn method_ident(kind: MethodKind, selector: &str) -> String {
+    format!("{}{}", kind.prefix(), selector.replace(":", "_"))
+}
+
+/// Returns the kind and selector of a method identifier, or `None` if it is
+/// not a `c_` or `i_` prefixed identifier for a representable selector.
+pub fn ident_to_selector(ident: &str) -> Option<(MethodKind, String)> {
+    let kind = if ident.starts_with(MethodKind::Class.prefix()) {
+        MethodKind::Class
+    } else if ident.starts_with(MethodKind::Instance.prefix()) {
+        MethodKind::Instance
+    } else {
+        return None;
+    };
+    let body = ident[kind.prefix().len()..];
+    let leading = body.len() - body.trim_left_chars('_').len();
+    let selector = format!("{}{}", body[..leading], body[leading..].replace("_", ":"));
+    if is_representable(selector.as_slice()) { Some((kind, selector)) } else { None }
+}
+
+/// Returns the selector of the setter for a property, such as `setFoo:`
+/// for `foo`.
+pub fn setter_selector(property: &str) -> String {
+    match property.slice_shift_char() {
+        Some((first, rest)) => format!("set{}{}:", first.to_uppercase(), rest),
+        None => "set:".to_string(),
+    }
+}
+
+/// Returns the identifier of a property's getter, such as `i_foo`.
+pub fn property_getter_ident(property: &str) -> String {
+    method_ident(MethodKind::Instance, property)
+}
+
+/// Returns the identifier of a property's setter, such as `i_setFoo_`.
+pub fn property_setter_ident(property: &str) -> String {
+    method_ident(MethodKind::Instance, setter_selector(property).as_slice())
+}
+
+/// Returns the identifier of an instance variable, such as `iv__count`.
+pub fn ivar_ident(ivar: &str) -> String {
+    format!("iv_{}", ivar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MethodKind, escape_keyword, ident_to_selector, is_representable, method_ident};
+    use super::{property_getter_ident, property_setter_ident};
+
+    #[test]
+    pub fn test_readme_table() {
+        assert_eq!(method_ident(MethodKind::Class, "newFoo:").as_slice(), "c_newFoo_");
+        assert_eq!(method_ident(MethodKind::Class, "newFoo:withBar:").as_slice(), "c_newFoo_withBar_");
+        assert_eq!(method_ident(MethodKind::Instance, "setFoo:").as_slice(), "i_setFoo_");
+        assert_eq!(method_ident(MethodKind::Instance, "setFoo:withBar:").as_slice(), "i_setFoo_withBar_");
+        assert_eq!(ident_to_selector("c_newFoo_withBar_"), Some((MethodKind::Class, "newFoo:withBar:".to_string())));
+        assert_eq!(ident_to_selector("i_setFoo_"), Some((MethodKind::Instance, "setFoo:".to_string())));
+    }
+
+    #[test]
+    pub fn test_properties() {
+        assert_eq!(property_getter_ident("foo").as_slice(), "i_foo");
+        assert_eq!(property_setter_ident("foo").as_slice(), "i_setFoo_");
+    }
+
+    #[test]
+    pub fn test_leading_underscores() {
+        assert_eq!(method_ident(MethodKind::Instance, "_setFoo:").as_slice(), "i__setFoo_");
+        assert_eq!(ident_to_selector("i__setFoo_"), Some((MethodKind::Instance, "_setFoo:".to_string())));
+        assert_eq!(ident_to_selector("c___foo"), Some((MethodKind::Class, "__foo".to_string())));
+    }
+
+    #[test]
+    pub fn test_invalid() {
+        assert!(!is_representable("foo_bar:"));
+        assert!(!is_representable(":"));
+        assert!(!is_representable("_"));
+        assert!(!is_representable(""));
+        assert_eq!(ident_to_selector("x_foo"), None);
+        assert_eq!(ident_to_selector("i_"), None);
+        assert_eq!(ident_to_selector("i___"), None);
+    }
+
+    #[test]
+    pub fn test_keywords() {
+        assert_eq!(escape_keyword("type").as_slice(), "type_");
+        assert_eq!(escape_keyword("length").as_slice(), "length");
+        assert_eq!(method_ident(MethodKind::Instance, "type").as_slice(), "i_type");
+    }
+
+    /// Calls `f` with every string of up to `len` characters from `alphabet`.
+    fn each_string(alphabet: &[char], len: uint, f: |&str|) {
+        let mut strings = vec![String::new()];
+        for _ in range(0, len) {
+            let mut next = Vec::new();
+            for s in strings.iter() {
+                for &c in alphabet.iter() {
+                    let mut s = s.clone();
+                    s.push(c);
+                    f(s.as_slice());
+                    next.push(s);
+                }
+            }
+            strings = next;
+        }
+    }
+
+    #[test]
+    pub fn test_round_trip_exhaustive() {
+        each_string(&['a', 'Z', '1', '_', ':'], 5, |selector| {
+            for &kind in [MethodKind::Class, MethodKind::Instance].iter() {
+                let ident = method_ident(kind, selector);
+                if is_representable(selector) {
+                    assert_eq!(ident_to_selector(ident.as_slice()), Some((kind, selector.to_string())));
+                } else {
+                    assert!(ident_to_selector(ident.as_slice()) != Some((kind, selector.to_string())));
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,130 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Dynamic method resolution.
+//!
+//! When a class has no method for a selector, the runtime sends it
+//! `+resolveInstanceMethod:` or `+resolveClassMethod:` before forwarding the
+//! message, giving it a chance to add the method. A resolver is a Rust
+//! closure implementing these for a declared class:
+//!
+//! ~~~rust
+//! set_instance_method_resolver(proxy_class, |&: class: Class, name: Selector| {
+//!     match lookup(name) {
+//!         Some(imp) => class.add_raw_method(name, imp, "@@:"),
+//!         None => false,
+//!     }
+//! });
+//! ~~~
+//!
+//! Once a method has been added, later messages with its selector no longer
+//! reach the resolver.
+
+use {Class, Id, Selector, ffi, selector};
+
+/// Sets the closure that resolves unknown instance methods of `class` and
+/// its subclasses, by overriding `+resolveInstanceMethod:`.
+///
+/// The resolver is called with the class that received the message and the
+/// selector, and returns `true` if it added a method for the selector. If it
+/// returns `false`, the superclass's `+resolveInstanceMethod:` is tried.
+///
+/// Returns `false` if `class` already defines `+resolveInstanceMethod:`.
+pub unsafe fn set_instance_method_resolver<F>(class: Class, resolver: F) -> bool
+        where F: Fn(Class, Selector) -> bool + 'static {
+    add_resolver(class, "resolveInstanceMethod:", false, resolver)
+}
+
+/// Sets the closure that resolves unknown class methods of `class` and its
+/// subclasses, by overriding `+resolveClassMethod:`.
+///
+/// The resolver is called with the metaclass to add the method to and the
+/// selector, and returns `true` if it added a method for the selector. If it
+/// returns `false`, the superclass's `+resolveClassMethod:` is tried.
+///
+/// Returns `false` if `class` already defines `+resolveClassMethod:`.
+pub unsafe fn set_class_method_resolver<F>(class: Class, resolver: F) -> bool
+        where F: Fn(Class, Selector) -> bool + 'static {
+    add_resolver(class, "resolveClassMethod:", true, resolver)
+}
+
+unsafe fn add_resolver<F>(class: Class, name: &'static str, is_class: bool, resolver: F) -> bool
+        where F: Fn(Class, Selector) -> bool + 'static {
+    let meta = class.get_meta_class();
+    meta.add_method_fn(selector(name), "c@::", move |&: this: Id, sel: Selector| -> i8 {
+        // `this` is the class that received the message, which may be a
+        // subclass of `class`.
+        let receiver = Class { raw: this.raw as ffi::Class };
+        let target = if is_class { receiver.get_meta_class() } else { receiver };
+        if resolver(target, sel) {
+            ffi::YES
+        } else {
+            msg_send_super!(meta, this, name, sel)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::c_int;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUint, SeqCst};
+
+    use super::{set_class_method_resolver, set_instance_method_resolver};
+    use {Class, Id, Selector, class, msg_send, selector};
+    use NSClassObject;
+
+    #[test]
+    pub fn test_instance_method_resolver() {
+        unsafe {
+            let test_class = class("NSObject").allocate_unique_class_pair("RSResolveInstanceTest", 0);
+            let calls = Arc::new(AtomicUint::new(0));
+            let resolver_calls = calls.clone();
+            assert!(set_instance_method_resolver(test_class, move |&: class: Class, name: Selector| {
+                resolver_calls.fetch_add(1, SeqCst);
+                name == selector("value") &&
+                    class.add_method_fn(name, "i@:", |&: _this: Id| 7 as c_int)
+            }));
+            test_class.register_class_pair();
+
+            let object = NSClassObject::c_new(test_class);
+            let value: c_int = msg_send()(object, selector("value"));
+            assert_eq!(value, 7);
+            let value: c_int = msg_send()(object, selector("value"));
+            assert_eq!(value, 7);
+            assert_eq!(calls.load(SeqCst), 1);
+
+            let responds: i8 = msg_send()(object, selector("respondsToSelector:"), selector("missing"));
+            assert_eq!(responds, 0);
+            assert_eq!(calls.load(SeqCst), 2);
+            assert!(!NSClassObject::c_resolveInstanceMethod_(test_class, selector("missing")));
+        }
+    }
+
+    #[test]
+    pub fn test_class_method_resolver() {
+        unsafe {
+            let test_class = class("NSObject").allocate_unique_class_pair("RSResolveClassTest", 0);
+            assert!(set_class_method_resolver(test_class, |&: meta: Class, name: Selector| {
+                assert!(meta.is_meta_class());
+                name == selector("value") &&
+                    meta.add_method_fn(name, "i@:", |&: _this: Id| 9 as c_int)
+            }));
+            test_class.register_class_pair();
+
+            let value: c_int = msg_send()(test_class.as_id(), selector("value"));
+            assert_eq!(value, 9);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Method swizzling that is undone when it goes out of scope.
+//!
+//! ~~~rust
+//! let swizzle = Swizzle::with_fn(class("NSObject"), selector("description"), |&: this: Id| {
+//!     ...
+//! }).unwrap();
+//! // `-[NSObject description]` calls the closure until `swizzle` is dropped.
+//! ~~~
+//!
+//! Swizzles of the same method must be dropped in the reverse order to that
+//! in which they were made.
+
+use imp::{IntoImp, IntoMethodBlock};
+use {Class, Impl, Method, Selector, implementation_with_block, remove_block};
+
+/// A replaced method implementation, restored when the `Swizzle` is dropped.
+///
+/// To swizzle a class method, pass the metaclass.
+pub struct Swizzle {
+    class: Class,
+    selector: Selector,
+    original: Impl,
+    replacement: Impl,
+    /// Whether the method was inherited, and added to `class` to override it.
+    added: bool,
+    /// Whether `replacement` was created by `implementation_with_block`.
+    owns_block: bool,
+}
+
+impl Swizzle {
+    /// Replaces the implementation of the instance method `selector` of
+    /// `class` with `imp`.
+    ///
+    /// If `class` inherits the method, an override is added to `class`, so
+    /// its superclasses are unaffected.
+    pub unsafe fn new<F: IntoImp>(class: Class, selector: Selector, imp: F) -> Result<Swizzle, String> {
+        let imp = imp.into_imp();
+        let method = class.get_instance_method(selector);
+        if method.is_null() {
+            return Err(format!("{} has no method {}", class, selector));
+        }
+        let method = Method { raw: method };
+
+        let sup = class.get_super_class();
+        let added = sup != Class::nil() && sup.get_instance_method(selector) == method.raw;
+        let original = if added {
+            let types = method.get_type_encoding();
+            if !class.add_raw_method(selector, imp, types.as_str().unwrap_or("")) {
+                return Err(format!("could not override {} on {}", selector, class));
+            }
+            method.get_implementation()
+        } else {
+            method.set_implementation(imp)
+        };
+
+        Ok(Swizzle {
+            class: class,
+            selector: selector,
+            original: original,
+            replacement: imp,
+            added: added,
+            owns_block: false,
+        })
+    }
+
+    /// Replaces the implementation of a method with a Rust closure, which is
+    /// called with the receiver followed by the method's arguments. See
+    /// `Class::add_method_fn`.
+    ///
+    /// The closure is released when the `Swizzle` is dropped, so it must not
+    /// be running on another thread at that point.
+    pub unsafe fn with_fn<A, R, F: IntoMethodBlock<A, R>>(class: Class, selector: Selector,
+                                                          closure: F) -> Result<Swizzle, String> {
+        let imp = implementation_with_block(&*closure.into_method_block());
+        match Swizzle::new(class, selector, imp) {
+            Ok(mut swizzle) => {
+                swizzle.owns_block = true;
+                Ok(swizzle)
+            }
+            Err(err) => {
+                remove_block(imp);
+                Err(err)
+            }
+        }
+    }
+
+    /// The swizzled class.
+    #[inline]
+    pub fn class(&self) -> Class {
+        self.class
+    }
+
+    /// The swizzled selector.
+    #[inline]
+    pub fn selector(&self) -> Selector {
+        self.selector
+    }
+
+    /// The implementation that was replaced, which the replacement may call
+    /// to forward the message. For an inherited method, this is the
+    /// superclass's implementation at the time of swizzling.
+    #[inline]
+    pub fn original(&self) -> Impl {
+        self.original
+    }
+
+    /// The replacement implementation.
+    #[inline]
+    pub fn replacement(&self) -> Impl {
+        self.replacement
+    }
+}
+
+impl Drop for Swizzle {
+    /// Restores the original implementation.
+    ///
+    /// The runtime cannot remove a method, so an override added for an
+    /// inherited method is instead given the superclass's current
+    /// implementation. Later changes to the superclass's method are not seen
+    /// by `class`.
+    fn drop(&mut self) {
+        unsafe {
+            let method = Method { raw: self.class.get_instance_method(self.selector) };
+            let restored = if self.added {
+                self.class.get_super_class().get_method_implementation(self.selector)
+            } else {
+                self.original
+            };
+            method.set_implementation(restored);
+            if self.owns_block {
+                remove_block(self.replacement);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::c_int;
+
+    use super::Swizzle;
+    use {Class, Id, Selector, class, msg_send, selector};
+    use NSClassObject;
+
+    unsafe fn value(object: Id) -> c_int {
+        msg_send()(object, selector("value"))
+    }
+
+    unsafe fn test_classes(base_name: &str, sub_name: &str) -> (Class, Class) {
+        let base = class("NSObject").allocate_unique_class_pair(base_name, 0);
+        assert!(base.add_method_fn(selector("value"), "i@:", |&: _this: Id| 1 as c_int));
+        base.register_class_pair();
+        let sub = base.allocate_unique_class_pair(sub_name, 0);
+        sub.register_class_pair();
+        (base, sub)
+    }
+
+    #[test]
+    pub fn test_swizzle_defined_method() {
+        unsafe {
+            let (base, _) = test_classes("RSSwizzleDefinedBase", "RSSwizzleDefinedSub");
+            let object = NSClassObject::c_new(base);
+            {
+                let swizzle = Swizzle::with_fn(base, selector("value"), |&: _this: Id| 2 as c_int).unwrap();
+                assert_eq!(value(object), 2);
+
+                let original: extern fn(Id, Selector) -> c_int = swizzle.original().cast();
+                assert_eq!(original(object, selector("value")), 1);
+            }
+            assert_eq!(value(object), 1);
+        }
+    }
+
+    #[test]
+    pub fn test_swizzle_inherited_method() {
+        unsafe {
+            let (base, sub) = test_classes("RSSwizzleInheritedBase", "RSSwizzleInheritedSub");
+            let base_object = NSClassObject::c_new(base);
+            let sub_object = NSClassObject::c_new(sub);
+            {
+                let _swizzle = Swizzle::with_fn(sub, selector("value"), |&: _this: Id| 2 as c_int).unwrap();
+                assert_eq!(value(sub_object), 2);
+                assert_eq!(value(base_object), 1);
+            }
+            assert_eq!(value(sub_object), 1);
+            assert_eq!(value(base_object), 1);
+        }
+    }
+
+    #[test]
+    pub fn test_swizzle_missing_method() {
+        unsafe {
+            let result = Swizzle::with_fn(class("NSObject"), selector("rs_noSuchMethod"), |&: _this: Id| ());
+            assert!(result.is_err());
+        }
+    }
+}
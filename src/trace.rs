@@ -0,0 +1,314 @@
+// Copyright 2014 the objc-rs developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Recording of the messages sent with `msg_send!`, available with the
+//! `trace` feature.
+//!
+//! Each message is recorded as an `Event` and passed to the active `Sink`:
+//!
+//! ~~~rust
+//! let buffer = RingBuffer::new(1024);
+//! trace::set_sink(Some(box buffer.clone()));
+//! let length: uint = msg_send!(string, "length");
+//! trace::set_sink(None);
+//!
+//! for event in buffer.events().iter() {
+//!     println!("{}", event);
+//! }
+//! ~~~
+//!
+//! Messages that throw an exception are not recorded, and neither are those
+//! sent with the `msg_send` functions directly.
+//! The `NSClassObject` and `forward::Invocation` bindings use `msg_send!`,
+//! but bindings produced by `objc-gen` and the exception machinery call the
+//! functions, so their messages are missing from traces.
+
+use serialize::json;
+use std::collections::RingBuf;
+use std::fmt;
+use std::io::{BufferedWriter, File, IoResult, stderr};
+use std::mem;
+use std::sync::{Arc, Mutex, MUTEX_INIT, StaticMutex};
+use std::sync::atomic::{AtomicBool, INIT_ATOMIC_BOOL, SeqCst};
+use time::precise_time_ns;
+
+use {Class, Id, Method, Selector, ffi};
+
+/// A message sent with `msg_send!`.
+#[deriving(Clone, PartialEq)]
+pub struct Event {
+    /// The name of the receiver's class, or `nil`.
+    pub class: String,
+    /// Whether the receiver was a class rather than an instance.
+    pub is_class: bool,
+    pub selector: String,
+    /// The type encodings of the arguments following the receiver and
+    /// selector, or empty if the receiver has no such method.
+    pub arguments: Vec<String>,
+    /// The type encoding of the return value, or empty if the receiver has
+    /// no such method.
+    pub ret: String,
+    /// When the message was sent, in nanoseconds from an arbitrary epoch.
+    pub start: u64,
+    /// How long the method took to return, in nanoseconds.
+    pub duration: u64,
+}
+
+impl Event {
+    /// Describes a message sent to an instance of `class`, which was found
+    /// before sending it, as was `method`; the receiver may be freed by the
+    /// message.
+    unsafe fn new(class: Class, selector: Selector, method: ffi::Method, start: u64, duration: u64) -> Event {
+        let (arguments, ret) = if method.is_null() {
+            (Vec::new(), String::new())
+        } else {
+            let method = Method { raw: method };
+            let arguments = range(2, method.get_number_of_arguments()).map(|i| {
+                method.copy_argument_type(i).as_str().unwrap_or("").to_string()
+            }).collect();
+            (arguments, method.copy_return_type().as_str().unwrap_or("").to_string())
+        };
+        Event {
+            class: class.to_string(),
+            is_class: class != Class::nil() && class.is_meta_class(),
+            selector: selector.get_name(),
+            arguments: arguments,
+            ret: ret,
+            start: start,
+            duration: duration,
+        }
+    }
+
+    /// The method in the form `-[NSString length]`.
+    pub fn method_name(&self) -> String {
+        format!("{}[{} {}]", if self.is_class { "+" } else { "-" }, self.class, self.selector)
+    }
+}
+
+impl fmt::Show for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({}) -> {} in {}ns", self.method_name(), self.arguments.connect(", "),
+               if self.ret.is_empty() { "?" } else { self.ret.as_slice() }, self.duration)
+    }
+}
+
+/// A destination for recorded events.
+///
+/// A sink must not itself send messages with `msg_send!`.
+pub trait Sink {
+    fn record(&self, event: &Event);
+}
+
+static ENABLED: AtomicBool = INIT_ATOMIC_BOOL;
+static SINK_LOCK: StaticMutex = MUTEX_INIT;
+static mut SINK: *mut Box<Sink + Send + Sync> = 0 as *mut Box<Sink + Send + Sync>;
+
+/// Sets the sink that events are recorded to, replacing and dropping the
+/// previous one. Recording stops if `sink` is `None`.
+pub fn set_sink(sink: Option<Box<Sink + Send + Sync>>) {
+    unsafe {
+        let _guard = SINK_LOCK.lock();
+        let old = SINK;
+        SINK = match sink {
+            Some(sink) => mem::transmute(box sink),
+            None => 0 as *mut Box<Sink + Send + Sync>,
+        };
+        ENABLED.store(!SINK.is_null(), SeqCst);
+        if !old.is_null() {
+            drop(mem::transmute::<_, Box<Box<Sink + Send + Sync>>>(old));
+        }
+    }
+}
+
+/// Calls `send`, which sends `selector` to `receiver`, recording the
+/// message to the active sink. This is used by `msg_send!`.
+pub unsafe fn trace_send<T, F: FnOnce() -> T>(receiver: Id, selector: Selector, send: F) -> T {
+    if !ENABLED.load(SeqCst) {
+        return send();
+    }
+    // Only plain data is live across `send`, which may throw an exception
+    // through this frame.
+    let class = receiver.get_class();
+    let method = class.get_instance_method(selector);
+    let start = precise_time_ns();
+    let result = send();
+    let duration = precise_time_ns() - start;
+
+    let event = Event::new(class, selector, method, start, duration);
+    let _guard = SINK_LOCK.lock();
+    if !SINK.is_null() {
+        (**SINK).record(&event);
+    }
+    result
+}
+
+/// A sink that keeps the most recent events in memory. Clones share the same
+/// buffer.
+#[deriving(Clone)]
+pub struct RingBuffer {
+    events: Arc<Mutex<RingBuf<Event>>>,
+    capacity: uint,
+}
+
+impl RingBuffer {
+    /// Creates a buffer holding up to `capacity` events.
+    pub fn new(capacity: uint) -> RingBuffer {
+        RingBuffer {
+            events: Arc::new(Mutex::new(RingBuf::with_capacity(capacity))),
+            capacity: capacity,
+        }
+    }
+
+    /// Returns the buffered events, oldest first.
+    pub fn events(&self) -> Vec<Event> {
+        self.events.lock().iter().map(|event| event.clone()).collect()
+    }
+
+    /// Discards the buffered events.
+    pub fn clear(&self) {
+        self.events.lock().clear();
+    }
+}
+
+impl Sink for RingBuffer {
+    fn record(&self, event: &Event) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut events = self.events.lock();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(event.clone());
+    }
+}
+
+/// A sink that writes each event to standard error.
+pub struct LogSink;
+
+impl Sink for LogSink {
+    fn record(&self, event: &Event) {
+        let _ = writeln!(&mut stderr(), "objc: {}", event);
+    }
+}
+
+/// A sink that writes events to a file in the Chrome trace event format,
+/// which can be viewed with `chrome://tracing`. The file is completed when
+/// the sink is dropped.
+pub struct ChromeTraceSink {
+    writer: Mutex<BufferedWriter<File>>,
+    is_first: AtomicBool,
+}
+
+#[deriving(Encodable)]
+struct ChromeTraceEvent {
+    name: String,
+    cat: &'static str,
+    ph: &'static str,
+    /// Microseconds.
+    ts: f64,
+    /// Microseconds.
+    dur: f64,
+    pid: uint,
+    tid: uint,
+    args: ChromeTraceArgs,
+}
+
+#[deriving(Encodable)]
+struct ChromeTraceArgs {
+    arguments: Vec<String>,
+    returns: String,
+}
+
+impl ChromeTraceSink {
+    /// Creates the trace file at `path`.
+    pub fn create(path: &Path) -> IoResult<ChromeTraceSink> {
+        let mut writer = BufferedWriter::new(try!(File::create(path)));
+        try!(writer.write_str("["));
+        Ok(ChromeTraceSink {
+            writer: Mutex::new(writer),
+            is_first: AtomicBool::new(true),
+        })
+    }
+}
+
+impl Sink for ChromeTraceSink {
+    fn record(&self, event: &Event) {
+        let trace_event = ChromeTraceEvent {
+            name: event.method_name(),
+            cat: "objc",
+            ph: "X",
+            ts: event.start as f64 / 1000.0,
+            dur: event.duration as f64 / 1000.0,
+            pid: 1,
+            tid: 1,
+            args: ChromeTraceArgs {
+                arguments: event.arguments.clone(),
+                returns: event.ret.clone(),
+            },
+        };
+        let separator = if self.is_first.swap(false, SeqCst) { "\n" } else { ",\n" };
+        let mut writer = self.writer.lock();
+        let _ = writer.write_str(separator);
+        let _ = writer.write_str(json::encode(&trace_event).as_slice());
+    }
+}
+
+impl Drop for ChromeTraceSink {
+    fn drop(&mut self) {
+        let mut writer = self.writer.lock();
+        let _ = writer.write_str("\n]\n");
+        let _ = writer.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RingBuffer, Sink, set_sink};
+    use {Id, class};
+
+    #[test]
+    pub fn test_trace() {
+        unsafe {
+            let buffer = RingBuffer::new(2);
+            set_sink(Some(box buffer.clone()));
+            let object: Id = msg_send!(class("NSObject").as_id(), "new");
+            let _: Id = msg_send!(object, "description");
+            let _: i8 = msg_send!(object, "isEqual:", object);
+            set_sink(None);
+            let _: Id = msg_send!(object, "description");
+
+            let events = buffer.events();
+            assert_eq!(events.len(), 2);
+            assert_eq!(events[0].method_name().as_slice(), "-[NSObject description]");
+            assert_eq!(events[0].ret.as_slice(), "@");
+            assert_eq!(events[1].method_name().as_slice(), "-[NSObject isEqual:]");
+            assert_eq!(events[1].arguments, vec!["@".to_string()]);
+
+            buffer.clear();
+            assert!(buffer.events().is_empty());
+            buffer.record(&events[0]);
+            assert_eq!(buffer.events(), vec![events[0].clone()]);
+
+            // Small numbers are tagged pointers, with no isa field to read.
+            let number: Id = msg_send!(class("NSNumber").as_id(), "numberWithInt:", 1i32);
+            buffer.clear();
+            set_sink(Some(box buffer.clone()));
+            let value: i32 = msg_send!(number, "intValue");
+            set_sink(None);
+            assert_eq!(value, 1);
+            assert!(buffer.events()[0].method_name().as_slice().ends_with(" intValue]"));
+        }
+    }
+}